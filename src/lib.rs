@@ -8,6 +8,7 @@
 //! 4. A derive macro that derives the encodable trait [SyscallEncodable].
 //! 5. A trait that provides much lower overhead than the normal encoding, but may be harder to use and more limited [api::SyscallFastApi].
 //! 6. A way to encode pointers to other userland data structures that the kernel can verify before derefencing.
+//! 7. A derive macro for describing the pointers within a user data structure, so the kernel can copy it in one go [UserCopy].
 //!
 //!
 //! More documentation coming...
@@ -20,6 +21,17 @@
 //! #[derive(syscall_macros::SyscallEncodable, Debug, Clone, Eq, PartialEq, PartialOrd)]
 //! struct Bar<'a> { x: &'a u32 }
 //! ```
+//!
+//! # Forward Compatibility
+//! Derived enums fail to decode discriminants they don't know about. Marking an enum `#[syscall(non_exhaustive)]`
+//! instead decodes unknown discriminants into its `Unknown(u64)` variant, which is declared by hand and does not
+//! get a discriminant of its own. The `#[syscall_non_exhaustive]` attribute, placed before the derive, adds both the
+//! variant and the marker. The data of an unknown variant cannot be decoded, so this is best suited to enums
+//! that come last in their encoding, like error types.
+//!
+//! Deriving SyscallEncodable also derives a hash of the type's encoding (see `fingerprint::AbiHash`). ABIs that
+//! set `CHECK_FINGERPRINTS` send a fingerprint of each syscall's types along with its arguments, and the kernel
+//! rejects calls built against a different definition with `SyscallError::AbiMismatch`.
 
 #![allow(soft_unstable)]
 #![cfg_attr(any(test, feature = "benchmarking"), feature(test))]
@@ -28,7 +40,10 @@
 #[cfg(any(test, feature = "benchmarking"))]
 extern crate test;
 
-pub use syscall_encode_macros::SyscallEncodable;
+pub use syscall_encode_macros::{
+    syscall_handlers, syscall_non_exhaustive, ErrorCode, SyscallCommands, SyscallEncodable,
+    UserCopy,
+};
 pub use syscall_encode_traits::*;
 
 #[cfg(any(test, feature = "benchmarking"))]
@@ -50,31 +65,42 @@ pub mod tests {
             Allocation, SyscallAbi,
         },
         api::{SyscallApi, SyscallEncodable, SyscallFastApi},
-        encoder::SyscallEncoder,
+        encoder::{DecodeError, SyscallEncoder},
         error::SyscallError,
         ptr::{UserPointer, UserSlice},
-        syscall_api,
+        syscall_api, syscall_flags,
         table::SyscallTable,
     };
     const NR_REGS: usize = 6;
 
     type Register = u64;
 
-    type Encoder<'a> = RegistersAndStackEncoder<'a, NullAbi, Register, NR_REGS>;
+    type Encoder<'a, 'buf> = RegistersAndStackEncoder<'a, 'buf, NullAbi, Register, NR_REGS>;
     type EncodedType = RegisterAndStackData<Register, NR_REGS>;
 
     type Sender<T> = Arc<Mutex<std::sync::mpsc::Sender<T>>>;
     type Receiver<T> = Arc<Mutex<std::sync::mpsc::Receiver<T>>>;
 
+    /// An "object" that NullAbi translates pointers into for ObjectPointer.
+    static TEST_OBJECT: [u32; 4] = [1, 2, 3, 4];
+    const TEST_OBJECT_ID: u128 = 0x1234;
+
+    /// The ABI used by the tests, which passes calls between threads. Only checks fingerprints if FINGERPRINTS is
+    /// set, see [FingerprintAbi].
     #[allow(dead_code)]
-    pub struct NullAbi {
+    pub struct TestAbi<const FINGERPRINTS: bool> {
         arg_sender: Sender<(Register, EncodedType)>,
         ret_sender: Sender<EncodedType>,
         arg_receiver: Receiver<(Register, EncodedType)>,
         ret_receiver: Receiver<EncodedType>,
     }
 
-    impl Default for NullAbi {
+    pub type NullAbi = TestAbi<false>;
+
+    /// A [TestAbi] that sends fingerprints with each call, for testing ABI mismatches.
+    pub type FingerprintAbi = TestAbi<true>;
+
+    impl<const FINGERPRINTS: bool> Default for TestAbi<FINGERPRINTS> {
         fn default() -> Self {
             let (args, argr) = std::sync::mpsc::channel();
             let (rets, retr) = std::sync::mpsc::channel();
@@ -92,41 +118,37 @@ pub mod tests {
         }
     }
 
-    impl SyscallAbi for NullAbi {
+    impl<const FINGERPRINTS: bool> SyscallAbi for TestAbi<FINGERPRINTS> {
         type SyscallArgType = EncodedType;
 
         type SyscallRetType = EncodedType;
 
         type SyscallNumType = Register;
 
-        type ArgEncoder<'a> = Encoder<'a>
+        type ArgEncoder<'a, 'buf>
+            = RegistersAndStackEncoder<'a, 'buf, Self, Register, NR_REGS>
         where
             Self: 'a;
 
-        type RetEncoder<'a> = Encoder<'a>
+        type RetEncoder<'a, 'buf>
+            = RegistersAndStackEncoder<'a, 'buf, Self, Register, NR_REGS>
         where
             Self: 'a;
 
+        const CHECK_FINGERPRINTS: bool = FINGERPRINTS;
+
         fn with_alloc<F, R, E: Copy>(
             &self,
             layout: std::alloc::Layout,
             f: F,
         ) -> Result<R, SyscallError<E>>
         where
-            F: FnOnce(Allocation) -> Result<R, SyscallError<E>>,
+            F: FnOnce(Allocation<'_>) -> Result<R, SyscallError<E>>,
         {
             #[cfg(miri)]
             {
-                let mut region = Box::new([0u8; 256]);
-                let ptr = &mut *region as *mut [u8; 256];
-                let _off = ptr.align_offset(layout.align());
-
-                let _off = 0;
-                let size = 256 - _off;
-                if size < layout.size() {
-                    return Err(SyscallError::AllocationError);
-                }
-                f(Allocation::from(&mut region[_off..(_off + size)]))
+                use syscall_encode_traits::spill::{SpillAllocator, StackSpill};
+                StackSpill::<256>.with_spill(layout, f)
             }
             #[cfg(not(miri))]
             {
@@ -134,7 +156,25 @@ pub mod tests {
             }
         }
 
-        unsafe fn kernel_alloc(&self, _layout: std::alloc::Layout) -> Allocation {
+        fn addr_to_object(&self, addr: usize) -> Option<(u128, u64)> {
+            let base = TEST_OBJECT.as_ptr() as usize;
+            let offset = addr.checked_sub(base)?;
+            (offset < size_of_val(&TEST_OBJECT)).then_some((TEST_OBJECT_ID, offset as u64))
+        }
+
+        fn object_to_addr(&self, id: u128, offset: u64) -> Option<usize> {
+            (id == TEST_OBJECT_ID).then(|| TEST_OBJECT.as_ptr() as usize + offset as usize)
+        }
+
+        fn versioned_num(&self, num: Register, version: u32) -> Register {
+            num | (version as Register) << 32
+        }
+
+        fn split_versioned_num(&self, num: Register) -> (Register, u32) {
+            (num & 0xffffffff, (num >> 32) as u32)
+        }
+
+        unsafe fn kernel_alloc(&self, _layout: std::alloc::Layout) -> Allocation<'_> {
             Allocation::null()
         }
 
@@ -163,8 +203,8 @@ pub mod tests {
     struct NullHandler {
         abi: Arc<NullAbi>,
     }
-    impl<'a> SyscallTable<NullAbi> for NullHandler {
-        fn handle_call(&self, num: Register, arg: EncodedType) -> EncodedType {
+    impl SyscallTable<NullAbi> for NullHandler {
+        fn handle_call(&self, _ctx: &mut (), num: Register, arg: EncodedType) -> EncodedType {
             unsafe {
                 syscall_api! {
                     number = num;
@@ -256,15 +296,25 @@ pub mod tests {
     #[repr(C)]
     pub struct FooRet;
 
-    #[derive(SyscallEncodable, Clone, Copy, Debug)]
+    #[derive(SyscallEncodable, syscall_encode_macros::ErrorCode, Clone, Copy, Debug, PartialEq)]
     #[repr(C)]
     pub enum SimpleErr {
+        #[code = 1]
+        #[errno(EAGAIN)]
         Sad,
+        #[code = 7]
+        #[message = "very sad"]
+        #[errno = 125]
+        VerySad,
     }
 
     pub fn test_encode<
         'a,
-        T: PartialEq + Clone + Copy + Debug + SyscallEncodable<'a, NullAbi, EncodedType, Encoder<'a>>,
+        T: PartialEq
+            + Clone
+            + Copy
+            + Debug
+            + for<'b> SyscallEncodable<'a, NullAbi, EncodedType, Encoder<'a, 'b>>,
     >(
         abi: &'a Arc<NullAbi>,
         item: T,
@@ -287,6 +337,27 @@ pub mod tests {
         .unwrap();
     }
 
+    /// Encode item, and then decode the result as a (possibly different) type.
+    pub fn test_reencode<
+        'a,
+        T: Copy + for<'b> SyscallEncodable<'a, NullAbi, EncodedType, Encoder<'a, 'b>>,
+        U: Copy + SyscallEncodable<'a, NullAbi, EncodedType, Encoder<'a, 'static>>,
+    >(
+        abi: &'a Arc<NullAbi>,
+        item: T,
+    ) -> Result<U, DecodeError> {
+        let layout = core::alloc::Layout::new::<T>();
+        abi.with_alloc(layout, |alloc| {
+            let mut encoder = abi.arg_encoder(alloc);
+            item.encode(&mut encoder).unwrap();
+            let encoded = encoder.finish();
+
+            let mut decoder = abi.arg_decoder(encoded);
+            Result::<_, SyscallError<()>>::Ok(U::decode(&mut decoder))
+        })
+        .unwrap()
+    }
+
     pub fn test_encode_fast<
         'a,
         T: PartialEq + Clone + Copy + Debug + SyscallFastApi<'a, NullAbi>,
@@ -400,7 +471,8 @@ pub mod tests {
             let handler = NullHandler { abi: abi2 };
             let (num, args) = handler.abi.arg_receiver.lock().unwrap().recv().unwrap();
 
-            let ret = <NullHandler as SyscallTable<NullAbi>>::handle_call(&handler, num, args);
+            let ret =
+                <NullHandler as SyscallTable<NullAbi>>::handle_call(&handler, &mut (), num, args);
             handler.abi.ret_sender.lock().unwrap().send(ret).unwrap();
         });
 
@@ -468,7 +540,8 @@ pub mod tests {
             let handler = NullHandler { abi: abi2 };
             let (num, args) = handler.abi.arg_receiver.lock().unwrap().recv().unwrap();
 
-            let ret = <NullHandler as SyscallTable<NullAbi>>::handle_call(&handler, num, args);
+            let ret =
+                <NullHandler as SyscallTable<NullAbi>>::handle_call(&handler, &mut (), num, args);
             handler.abi.ret_sender.lock().unwrap().send(ret).unwrap();
         });
         let bar = Bar { x: 32, y: 1 };
@@ -477,4 +550,1605 @@ pub mod tests {
 
         thr.join().unwrap();
     }
+
+    syscall_flags! {
+        pub struct TestFlags: u32 {
+            const READ = 1;
+            const WRITE = 2;
+            const EXEC = 1 << 4;
+        }
+    }
+
+    syscall_flags! {
+        unknown_bits = retain;
+        pub struct TestCompatFlags: u32 {
+            const READ = 1;
+        }
+    }
+
+    #[test]
+    fn test_flags() {
+        let abi = Arc::new(NullAbi::default());
+        let flags = TestFlags::READ | TestFlags::EXEC;
+        assert!(flags.contains(TestFlags::READ));
+        assert!(!flags.contains(TestFlags::READ | TestFlags::WRITE));
+        assert_eq!(flags - TestFlags::READ, TestFlags::EXEC);
+        assert_eq!(!flags, TestFlags::WRITE);
+        test_encode(&abi, flags, |_, _| {});
+
+        let res: Result<TestFlags, _> = test_reencode(&abi, 0x13u32);
+        assert_eq!(res, Ok(TestFlags::all()));
+        let res: Result<TestFlags, _> = test_reencode(&abi, 0x8u32);
+        assert_eq!(res, Err(DecodeError::InvalidData));
+
+        let res: TestCompatFlags = test_reencode(&abi, 0x9u32).unwrap();
+        assert!(res.contains(TestCompatFlags::READ));
+        assert_eq!(res.unknown_bits(), 0x8);
+        test_encode(&abi, res, |_, decoded| assert_eq!(decoded.bits(), 0x9));
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_inline() {
+        use syscall_encode_traits::inline::{InlineBytes, InlineStr, SmallSlice};
+
+        let abi = Arc::new(NullAbi::default());
+        let name = InlineStr::<16>::new("hello").unwrap();
+        test_encode(&abi, name, |_, decoded| {
+            assert_eq!(decoded.as_str(), "hello")
+        });
+        assert!(InlineStr::<4>::new("hello").is_none());
+
+        let bad = InlineBytes::<16>::new(&[0xff, 0xfe]).unwrap();
+        let res: Result<InlineStr<16>, _> = test_reencode(&abi, bad);
+        assert_eq!(res, Err(DecodeError::InvalidData));
+        let res: Result<InlineBytes<4>, _> = test_reencode(&abi, name);
+        assert_eq!(res, Err(DecodeError::InvalidData));
+
+        let data = [1u32, 2, 3];
+        let small = SmallSlice::<u32, 4>::new(&data);
+        assert!(small.is_inline());
+        test_encode(&abi, small, |_, decoded| {
+            assert_eq!(decoded.as_ref(|_, _| false), Some(data.as_slice()))
+        });
+        let res: Result<SmallSlice<u32, 2>, _> = test_reencode(&abi, small);
+        assert_eq!(res, Err(DecodeError::InvalidData));
+        let spilled = SmallSlice::<u32, 2>::new(&data);
+        assert!(!spilled.is_inline());
+        test_encode(&abi, spilled, |_, decoded| {
+            assert_eq!(decoded.as_ref(|_, _| true), Some(data.as_slice()))
+        });
+    }
+
+    #[cfg(all(feature = "alloc", not(miri)))]
+    #[test]
+    fn test_owned() {
+        use syscall_encode_traits::{
+            owned::{OwnedCopyError, OwnedString, OwnedVec},
+            ptr::UserStr,
+        };
+
+        let abi = Arc::new(NullAbi::default());
+        let ids = vec![1u32, 2, 3];
+        test_encode(&abi, UserSlice::from(&ids), |_, decoded| {
+            assert_eq!(decoded.to_vec(3, |_, _| true), Ok(ids.clone()));
+            assert_eq!(decoded.to_vec(2, |_, _| true), Err(OwnedCopyError::TooLong));
+            assert_eq!(
+                decoded.to_vec(3, |_, _| false),
+                Err(OwnedCopyError::InvalidPointer)
+            );
+        });
+
+        let name = String::from("config");
+        test_encode(&abi, UserStr::from(&name), |_, decoded| {
+            assert_eq!(decoded.to_string(16, |_, _| true), Ok(name.clone()));
+            assert_eq!(
+                decoded.to_string(4, |_, _| true),
+                Err(OwnedCopyError::TooLong)
+            );
+        });
+        let bad = vec![0xffu8, 0xfe];
+        let bad: UserStr = test_reencode(&abi, UserSlice::from(&bad)).unwrap();
+        assert_eq!(
+            bad.to_string(16, |_, _| true),
+            Err(OwnedCopyError::InvalidUtf8)
+        );
+
+        let blob = Box::new(7u64);
+        test_encode(&abi, UserPointer::from(&blob), |_, decoded| {
+            assert_eq!(decoded.to_box(|_, _| true), Ok(blob.clone()));
+        });
+
+        // Limits in the argument's type are checked when decoding, before anything is copied.
+        let arg = OwnedVec::<u32, 3>::new(&ids).unwrap();
+        test_encode(&abi, arg, |_, decoded| {
+            assert_eq!(decoded.to_vec(|_, _| true), Ok(ids.clone()));
+        });
+        assert!(OwnedVec::<u32, 2>::new(&ids).is_none());
+        let res: Result<OwnedVec<u32, 2>, _> = test_reencode(&abi, UserSlice::from(&ids));
+        assert_eq!(res.err(), Some(DecodeError::InvalidData));
+
+        let arg = OwnedString::<16>::new(&name).unwrap();
+        test_encode(&abi, arg, |_, decoded| {
+            assert_eq!(decoded.to_string(|_, _| true), Ok(name.clone()));
+        });
+        let res: Result<OwnedString<4>, _> = test_reencode(&abi, UserStr::from(&name));
+        assert_eq!(res.err(), Some(DecodeError::InvalidData));
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_user_str() {
+        use syscall_encode_traits::ptr::{UserCStr, UserStr, UserStrError};
+
+        let abi = Arc::new(NullAbi::default());
+        let s = UserStr::new("/some/path");
+        test_encode(&abi, s, |_, decoded| {
+            let mut buf = [0u8; 16];
+            assert_eq!(decoded.copy_into(&mut buf, |_, _| true), Ok("/some/path"));
+            assert_eq!(
+                decoded.copy_into(&mut buf[..4], |_, _| true),
+                Err(UserStrError::TooLong)
+            );
+            assert_eq!(
+                decoded.as_ref(|_, _| false),
+                Err(UserStrError::InvalidPointer)
+            );
+        });
+
+        let bad = [0xffu8, 0xfe];
+        let bad: UserStr = test_reencode(&abi, UserSlice::new(&bad)).unwrap();
+        assert_eq!(bad.as_ref(|_, _| true), Err(UserStrError::InvalidUtf8));
+
+        let cs = c"name";
+        let s = UserCStr::new(cs);
+        test_encode(&abi, s, |_, decoded| {
+            let mut buf = [0u8; 16];
+            assert_eq!(decoded.copy_into(&mut buf, 64, |_, _| true), Ok(cs));
+            assert_eq!(decoded.copy_str_into(&mut buf, 64, |_, _| true), Ok("name"));
+            assert_eq!(
+                decoded.copy_into(&mut buf[..3], 64, |_, _| true),
+                Err(UserStrError::TooLong)
+            );
+            assert_eq!(
+                decoded.copy_into(&mut buf, 2, |_, _| true),
+                Err(UserStrError::Unterminated)
+            );
+        });
+
+        let s = UserCStr::new(c"\xff\xfe");
+        let mut buf = [0u8; 16];
+        assert_eq!(
+            s.copy_str_into(&mut buf, 64, |_, _| true),
+            Err(UserStrError::InvalidUtf8)
+        );
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_user_iovec() {
+        use syscall_encode_traits::iovec::{IoVec, IoVecError, UserIoVec};
+
+        let abi = Arc::new(NullAbi::default());
+        let a = [1u8, 2, 3];
+        let b = [4u8, 5];
+        let mut storage = [IoVec::default(); 4];
+        let iov = UserIoVec::from_slices(&[&a, &[], &b], &mut storage).unwrap();
+        test_encode(&abi, iov, |_, decoded| {
+            let mut kstorage = [IoVec::default(); 8];
+            let mut cursor = decoded.cursor(&mut kstorage, |_, _| true).unwrap();
+            assert_eq!(cursor.total_len(), 5);
+            let mut buf = [0u8; 4];
+            assert_eq!(cursor.copy_in(&mut buf), Ok(4));
+            assert_eq!(buf, [1, 2, 3, 4]);
+            assert_eq!(cursor.copy_in(&mut buf), Ok(1));
+            assert_eq!(buf[0], 5);
+            // The cursor works from the kernel-side copy of the segments.
+            assert_eq!(kstorage[..3].iter().map(IoVec::len).sum::<usize>(), 5);
+
+            assert_eq!(
+                decoded
+                    .cursor(&mut [IoVec::default(); 2], |_, _| true)
+                    .err(),
+                Some(IoVecError::TooManySegments)
+            );
+            assert_eq!(
+                decoded.cursor(&mut kstorage, |_, _| false).err(),
+                Some(IoVecError::InvalidPointer)
+            );
+        });
+
+        let mut x = [0u8; 2];
+        let mut y = [0u8; 3];
+        let mut bufs: [&mut [u8]; 2] = [&mut x, &mut y];
+        let mut storage = [IoVec::default(); 2];
+        let iov = UserIoVec::from_mut_slices(&mut bufs, &mut storage).unwrap();
+        let mut kstorage = [IoVec::default(); 2];
+        let mut cursor = iov.cursor(&mut kstorage, |_, _| true).unwrap();
+        assert_eq!(cursor.copy_out(&[9, 8, 7, 6, 5, 4]), Ok(5));
+        assert_eq!(x, [9, 8]);
+        assert_eq!(y, [7, 6, 5]);
+    }
+
+    #[derive(syscall_encode_macros::UserCopy, Clone, Copy, Debug, PartialEq, Eq)]
+    #[repr(C)]
+    pub struct Node<'a> {
+        val: u32,
+        name: UserSlice<'a, u8>,
+        next: UserPointer<'a, Node<'a>>,
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_deep_copy() {
+        use syscall_encode_traits::deep_copy::DeepCopyError;
+
+        let tail = Node {
+            val: 2,
+            name: UserSlice::new(b"tail"),
+            next: UserPointer::new_null(),
+        };
+        let head = Node {
+            val: 1,
+            name: UserSlice::new(b"head"),
+            next: UserPointer::new(&tail),
+        };
+        let ptr = UserPointer::new(&head);
+
+        let mut buf = [0u8; 256];
+        let mut regions = 0;
+        let copy = ptr
+            .deep_copy_in(&mut buf, 4, |_, _| {
+                regions += 1;
+                true
+            })
+            .unwrap();
+        assert_eq!(regions, 4);
+        assert_eq!(copy.val, 1);
+        assert_eq!(copy.name.as_ref(|_, _| true), Some(b"head".as_slice()));
+        let next = copy.next.as_ref(|_, _| true).unwrap();
+        assert_eq!(next.val, 2);
+        assert_eq!(next.name.as_ref(|_, _| true), Some(b"tail".as_slice()));
+        assert!(next.next.is_null());
+
+        let mut buf = [0u8; 256];
+        assert_eq!(
+            ptr.deep_copy_in(&mut buf, 1, |_, _| true).err(),
+            Some(DeepCopyError::TooDeep)
+        );
+        let mut buf = [0u8; 32];
+        assert_eq!(
+            ptr.deep_copy_in(&mut buf, 4, |_, _| true).err(),
+            Some(DeepCopyError::TooLarge)
+        );
+
+        // Copies are checked against their type's invariants before they are exposed.
+        let abi = Arc::new(NullAbi::default());
+        let bits = 0x100u32;
+        let flags: UserPointer<TestFlags> = test_reencode(&abi, UserPointer::new(&bits)).unwrap();
+        assert_eq!(
+            flags.deep_copy_in(&mut buf, 1, |_, _| true).err(),
+            Some(DeepCopyError::InvalidData)
+        );
+    }
+
+    #[test]
+    fn test_error_codes() {
+        use syscall_encode_traits::error::{errno, ErrorCode};
+
+        assert_eq!(SimpleErr::VerySad.code(), 7);
+        assert_eq!(SimpleErr::from_code(1), Some(SimpleErr::Sad));
+        assert_eq!(SimpleErr::from_code(2), None);
+
+        let e = SyscallError::SyscallError(SimpleErr::VerySad);
+        assert_eq!(SyscallError::from_raw(e.into_raw()), Some(e));
+        assert!(e.into_raw() < 0);
+        assert_eq!(e.to_string(), "very sad");
+        assert_eq!(e.errno(), errno::ECANCELED);
+        let e = SyscallError::SyscallError(SimpleErr::Sad);
+        assert_eq!(e.to_string(), "Sad");
+        assert_eq!(e.errno(), errno::EAGAIN);
+
+        let e = SyscallError::<SimpleErr>::InvalidNum;
+        assert_eq!(SyscallError::from_raw(e.into_raw()), Some(e));
+        assert_eq!(e.errno(), errno::ENOSYS);
+        assert_eq!(SyscallError::<SimpleErr>::from_raw(5), None);
+    }
+
+    #[derive(SyscallEncodable, syscall_encode_macros::ErrorCode, Clone, Copy, Debug, PartialEq)]
+    #[repr(C)]
+    #[syscall(non_exhaustive)]
+    pub enum CompatErr {
+        #[code = 1]
+        Sad,
+        Unknown(u64),
+    }
+
+    #[syscall_encode_macros::syscall_non_exhaustive]
+    #[derive(SyscallEncodable, Clone, Copy, Debug, PartialEq)]
+    #[repr(C)]
+    pub enum GeneratedCompatErr {
+        Sad,
+    }
+
+    #[test]
+    fn test_non_exhaustive() {
+        use syscall_encode_traits::error::{ErrorCode, UNKNOWN_CODE};
+
+        let abi = Arc::new(NullAbi::default());
+        let res: Result<CompatErr, _> = test_reencode(&abi, SimpleErr::VerySad);
+        assert_eq!(res, Ok(CompatErr::Unknown(1)));
+        let res: Result<SimpleErr, _> = test_reencode(&abi, CompatErr::Unknown(1));
+        assert_eq!(res, Ok(SimpleErr::VerySad));
+        let res: Result<CompatErr, _> = test_reencode(&abi, SimpleErr::Sad);
+        assert_eq!(res, Ok(CompatErr::Sad));
+        assert_eq!(CompatErr::Unknown(1).code(), UNKNOWN_CODE);
+        assert_eq!(CompatErr::from_code(UNKNOWN_CODE), None);
+
+        let res: Result<SyscallError<SimpleErr>, _> = test_reencode(&abi, 0x20u8);
+        let e = res.unwrap();
+        assert_eq!(e, SyscallError::Unknown(0x20));
+        assert_eq!(SyscallError::from_raw(e.into_raw()), Some(e));
+        assert_eq!(SyscallError::<SimpleErr>::from_raw(-0x12), None);
+        test_encode(&abi, e, |_, _| {});
+
+        // Raw codes of unknown discriminants don't overlap with those of the syscall's own errors. Discriminants
+        // up to 10 belong to SyscallError's variants, and are never decoded as unknown.
+        for dis in 0..=u8::MAX {
+            let e = SyscallError::<SimpleErr>::Unknown(dis);
+            assert_eq!(
+                SyscallError::from_raw(e.into_raw()),
+                (dis > 10).then_some(e)
+            );
+        }
+        for e in [SimpleErr::Sad, SimpleErr::VerySad] {
+            let e = SyscallError::SyscallError(e);
+            assert_eq!(SyscallError::from_raw(e.into_raw()), Some(e));
+        }
+
+        let res: Result<GeneratedCompatErr, _> = test_reencode(&abi, SimpleErr::VerySad);
+        assert_eq!(res, Ok(GeneratedCompatErr::Unknown(1)));
+    }
+
+    #[derive(SyscallEncodable, Clone, Copy, Debug, PartialEq, Eq)]
+    #[repr(C)]
+    pub struct Versioned {
+        x: u32,
+        y: u32,
+    }
+
+    impl<'a, const FINGERPRINTS: bool> SyscallApi<'a, TestAbi<FINGERPRINTS>> for Versioned {
+        const NUM: Register = 5;
+        const VERSION: u32 = 2;
+        type ReturnType = u32;
+        type ErrorType = SimpleErr;
+    }
+
+    #[derive(SyscallEncodable, Clone, Copy, Debug, PartialEq, Eq)]
+    #[repr(C)]
+    pub struct VersionedV1 {
+        x: u32,
+    }
+
+    impl<'a, const FINGERPRINTS: bool> SyscallApi<'a, TestAbi<FINGERPRINTS>> for VersionedV1 {
+        const NUM: Register = 5;
+        const VERSION: u32 = 1;
+        type ReturnType = u32;
+        type ErrorType = SimpleErr;
+    }
+
+    impl From<VersionedV1> for Versioned {
+        fn from(value: VersionedV1) -> Self {
+            Self { x: value.x, y: 10 }
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_versioning() {
+        struct VersionedHandler {
+            abi: FingerprintAbi,
+        }
+
+        impl SyscallTable<FingerprintAbi> for VersionedHandler {
+            fn handle_call(&self, _ctx: &mut (), num: Register, arg: EncodedType) -> EncodedType {
+                unsafe {
+                    syscall_api! {
+                        number = num;
+                        args = arg;
+                        abi_type = FingerprintAbi;
+                        abi = &self.abi;
+                        handlers = {
+                            (Versioned, |_n, v: Versioned| {
+                                Ok(v.x + v.y)
+                            }, upgrades = [VersionedV1])
+                        }
+                        fast_handlers = {}
+                    }
+                }
+            }
+        }
+
+        fn call<'a, T: SyscallApi<'a, FingerprintAbi>>(
+            handler: &'a VersionedHandler,
+            args: T,
+            version: u32,
+            fingerprint: u64,
+        ) -> Result<u32, SyscallError<SimpleErr>> {
+            let abi = &handler.abi;
+            let layout = core::alloc::Layout::new::<(u64, T)>();
+            abi.with_alloc(layout, |alloc| {
+                let mut encoder = abi.arg_encoder(alloc);
+                encoder.encode(&fingerprint).unwrap();
+                args.encode(&mut encoder).unwrap();
+                let num = abi.versioned_num(<T as SyscallApi<'a, FingerprintAbi>>::NUM, version);
+                let ret = handler.handle_call(&mut (), num, encoder.finish());
+                let mut decoder = abi.ret_decoder(ret);
+                let ret: Result<u32, SyscallError<SimpleErr>> = decoder.decode().unwrap();
+                Result::<_, SyscallError<()>>::Ok(ret)
+            })
+            .unwrap()
+        }
+
+        let handler = VersionedHandler {
+            abi: FingerprintAbi::default(),
+        };
+        let v2 = Versioned { x: 1, y: 2 };
+        let v1 = VersionedV1 { x: 1 };
+        let fp2 = <Versioned as SyscallApi<FingerprintAbi>>::FINGERPRINT;
+        let fp1 = <VersionedV1 as SyscallApi<FingerprintAbi>>::FINGERPRINT;
+        assert_eq!(call(&handler, v2, 2, fp2), Ok(3));
+        assert_eq!(call(&handler, v1, 1, fp1), Ok(11));
+        assert_eq!(call(&handler, v1, 0, fp1), Err(SyscallError::VersionTooOld));
+        assert_eq!(call(&handler, v2, 3, fp2), Err(SyscallError::VersionTooNew));
+        assert_eq!(call(&handler, v1, 1, fp2), Err(SyscallError::AbiMismatch));
+    }
+
+    #[cfg(all(feature = "dyn-table", not(miri)))]
+    #[test]
+    fn test_dyn_table() {
+        use syscall_encode_traits::table::dynamic::{DynHandler, DynSyscallTable, RegisterError};
+
+        fn call<'a, C: 'a, T: SyscallApi<'a, NullAbi>>(
+            table: &DynSyscallTable<'a, NullAbi, 8, C>,
+            ctx: &mut C,
+            abi: &'a NullAbi,
+            args: T,
+        ) -> Result<u32, SyscallError<SimpleErr>> {
+            let layout = core::alloc::Layout::new::<T>();
+            abi.with_alloc(layout, |alloc| {
+                let mut encoder = abi.arg_encoder(alloc);
+                args.encode(&mut encoder).unwrap();
+                let num = abi.versioned_num(
+                    <T as SyscallApi<'a, NullAbi>>::NUM,
+                    <T as SyscallApi<'a, NullAbi>>::VERSION,
+                );
+                let ret = table.handle_call(ctx, num, encoder.finish());
+                let mut decoder = abi.ret_decoder(ret);
+                let ret: Result<u32, SyscallError<SimpleErr>> = decoder.decode().unwrap();
+                Result::<_, SyscallError<()>>::Ok(ret)
+            })
+            .unwrap()
+        }
+
+        let abi = NullAbi::default();
+        let add = |_n, v: Versioned| Ok(v.x + v.y);
+        let mul = |_n, v: Versioned| Ok(v.x * v.y);
+        let fast = |_n, bar: Bar| Baz { a: bar.x == bar.y };
+        let add = DynHandler::new::<Versioned, _>(&add);
+        let mul = DynHandler::new::<Versioned, _>(&mul);
+        let fast = DynHandler::new_fast::<Bar, _>(&fast);
+
+        let table = DynSyscallTable::<NullAbi, 8>::new(&abi);
+        let args = Versioned { x: 2, y: 3 };
+        assert_eq!(
+            call(&table, &mut (), &abi, args),
+            Err(SyscallError::InvalidNum)
+        );
+
+        table.register(&add).unwrap();
+        table.register(&fast).unwrap();
+        assert_eq!(table.register(&mul), Err(RegisterError::Occupied));
+        assert_eq!(call(&table, &mut (), &abi, args), Ok(5));
+        let ret = table.handle_call(&mut (), Bar::NUM, Bar { x: 1, y: 1 }.into());
+        assert!(Baz::from(ret).a);
+
+        assert!(table.replace(&mul).unwrap().is_some());
+        assert_eq!(call(&table, &mut (), &abi, args), Ok(6));
+        assert!(table
+            .unregister(<Versioned as SyscallApi<NullAbi>>::NUM)
+            .is_some());
+        assert_eq!(
+            call(&table, &mut (), &abi, args),
+            Err(SyscallError::InvalidNum)
+        );
+
+        // Restartable handlers report interruptions as is.
+        let interrupted = |_n, _v: Versioned| Err(SyscallError::Interrupted);
+        let interrupted = DynHandler::new_restartable::<Versioned, _>(&interrupted);
+        table.register(&interrupted).unwrap();
+        assert_eq!(
+            call(&table, &mut (), &abi, args),
+            Err(SyscallError::Interrupted)
+        );
+
+        let small = DynSyscallTable::<NullAbi, 4>::new(&abi);
+        assert_eq!(small.register(&add), Err(RegisterError::OutOfRange));
+
+        let counted = |calls: &mut u32, _n, v: Versioned| {
+            *calls += 1;
+            Ok(v.x)
+        };
+        let counted = DynHandler::new_with_context::<Versioned, _>(&counted);
+        let counting = DynSyscallTable::<NullAbi, 8, u32>::new(&abi);
+        counting.register(&counted).unwrap();
+        let mut calls = 0;
+        assert_eq!(call(&counting, &mut calls, &abi, args), Ok(2));
+        assert_eq!(calls, 1);
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_syscall_handlers() {
+        use syscall_encode_macros::syscall_handlers;
+
+        #[syscall_handlers(abi = NullAbi)]
+        trait Kernel {
+            #[syscall(upgrades = [VersionedV1], restartable)]
+            fn add(&self, args: Versioned) -> Result<u32, SyscallError<SimpleErr>>;
+            #[syscall(fast)]
+            fn same(&self, args: Bar) -> Baz;
+        }
+
+        struct TestKernel {
+            scale: u32,
+        }
+
+        impl Kernel for TestKernel {
+            fn add(&self, args: Versioned) -> Result<u32, SyscallError<SimpleErr>> {
+                if args.x == 0 {
+                    return Err(SyscallError::Interrupted);
+                }
+                Ok((args.x + args.y) * self.scale)
+            }
+
+            fn same(&self, args: Bar) -> Baz {
+                Baz {
+                    a: args.x == args.y,
+                }
+            }
+        }
+
+        fn call<'a, T: SyscallApi<'a, NullAbi>>(
+            table: &KernelTable<'a, TestKernel>,
+            abi: &'a NullAbi,
+            args: T,
+        ) -> Result<u32, SyscallError<SimpleErr>> {
+            let layout = core::alloc::Layout::new::<T>();
+            abi.with_alloc(layout, |alloc| {
+                let mut encoder = abi.arg_encoder(alloc);
+                args.encode(&mut encoder).unwrap();
+                let num = abi.versioned_num(
+                    <T as SyscallApi<'a, NullAbi>>::NUM,
+                    <T as SyscallApi<'a, NullAbi>>::VERSION,
+                );
+                let ret = table.handle_call(&mut (), num, encoder.finish());
+                let mut decoder = abi.ret_decoder(ret);
+                let ret: Result<u32, SyscallError<SimpleErr>> = decoder.decode().unwrap();
+                Result::<_, SyscallError<()>>::Ok(ret)
+            })
+            .unwrap()
+        }
+
+        let abi = NullAbi::default();
+        let kernel = TestKernel { scale: 2 };
+        let table = KernelTable::new(&abi, &kernel);
+        assert_eq!(call(&table, &abi, Versioned { x: 1, y: 2 }), Ok(6));
+        assert_eq!(call(&table, &abi, VersionedV1 { x: 1 }), Ok(22));
+        assert_eq!(
+            call(&table, &abi, Versioned { x: 0, y: 2 }),
+            Err(SyscallError::Interrupted)
+        );
+        assert_eq!(
+            call(&table, &abi, Foo::default()),
+            Err(SyscallError::InvalidNum)
+        );
+        let ret = table.handle_call(&mut (), Bar::NUM, Bar { x: 1, y: 2 }.into());
+        assert!(!Baz::from(ret).a);
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_context() {
+        struct FakeThread {
+            id: u32,
+            calls: u32,
+        }
+
+        struct ContextHandler {
+            abi: NullAbi,
+        }
+
+        impl SyscallTable<NullAbi, FakeThread> for ContextHandler {
+            fn handle_call(
+                &self,
+                ctx: &mut FakeThread,
+                num: Register,
+                arg: EncodedType,
+            ) -> EncodedType {
+                unsafe {
+                    syscall_api! {
+                        number = num;
+                        args = arg;
+                        abi_type = NullAbi;
+                        abi = &self.abi;
+                        context = ctx;
+                        handlers = {
+                            (Versioned, |thread, _n, v| {
+                                thread.calls += 1;
+                                Ok(v.x + v.y + thread.id)
+                            }, upgrades = [VersionedV1])
+                        }
+                        fast_handlers = {
+                            (Bar, |thread, _n, bar| {
+                                thread.calls += 1;
+                                Baz { a: bar.x == thread.id }
+                            })
+                        }
+                    }
+                }
+            }
+        }
+
+        let handler = ContextHandler {
+            abi: NullAbi::default(),
+        };
+        let abi = &handler.abi;
+        let mut thread = FakeThread { id: 100, calls: 0 };
+        let args = VersionedV1 { x: 1 };
+        let ret = abi
+            .with_alloc(core::alloc::Layout::new::<VersionedV1>(), |alloc| {
+                let mut encoder = abi.arg_encoder(alloc);
+                args.encode(&mut encoder).unwrap();
+                let num = abi.versioned_num(
+                    <VersionedV1 as SyscallApi<NullAbi>>::NUM,
+                    <VersionedV1 as SyscallApi<NullAbi>>::VERSION,
+                );
+                let ret = handler.handle_call(&mut thread, num, encoder.finish());
+                let mut decoder = abi.ret_decoder(ret);
+                let ret: Result<u32, SyscallError<SimpleErr>> = decoder.decode().unwrap();
+                Result::<_, SyscallError<()>>::Ok(ret)
+            })
+            .unwrap();
+        assert_eq!(ret, Ok(111));
+        let ret = handler.handle_call(&mut thread, Bar::NUM, Bar { x: 100, y: 0 }.into());
+        assert!(Baz::from(ret).a);
+        assert_eq!(thread.calls, 2);
+
+        #[syscall_encode_macros::syscall_handlers(abi = NullAbi, context = FakeThread)]
+        trait ThreadKernel {
+            #[syscall(fast)]
+            fn same(&self, thread: &mut FakeThread, args: Bar) -> Baz;
+        }
+
+        struct TestKernel;
+
+        impl ThreadKernel for TestKernel {
+            fn same(&self, thread: &mut FakeThread, args: Bar) -> Baz {
+                thread.calls += 1;
+                Baz {
+                    a: args.x == thread.id,
+                }
+            }
+        }
+
+        let table = ThreadKernelTable::new(abi, &TestKernel);
+        let ret = table.handle_call(&mut thread, Bar::NUM, Bar { x: 1, y: 0 }.into());
+        assert!(!Baz::from(ret).a);
+        assert_eq!(thread.calls, 3);
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_mounts() {
+        use syscall_encode_macros::syscall_handlers;
+        use syscall_encode_traits::{
+            syscall_mounts,
+            table::{SyscallNamespace, SyscallTable},
+        };
+
+        #[syscall_handlers(abi = NullAbi)]
+        trait Sub {
+            fn add(&self, args: Versioned) -> Result<u32, SimpleErr>;
+        }
+
+        struct SubKernel;
+
+        impl Sub for SubKernel {
+            fn add(&self, args: Versioned) -> Result<u32, SimpleErr> {
+                Ok(args.x + args.y)
+            }
+        }
+
+        // Foo and Bar are in the core namespace, and Versioned is in the sub namespace.
+        struct CoreNs;
+        struct SubNs;
+
+        impl SyscallNamespace<NullAbi> for CoreNs {
+            const PREFIX: Register = 0;
+        }
+
+        impl SyscallNamespace<NullAbi> for SubNs {
+            const PREFIX: Register = 1;
+        }
+
+        struct Kernel<'a> {
+            abi: &'a NullAbi,
+            core: NullHandler,
+            sub: SubTable<'a, SubKernel>,
+        }
+
+        impl<'a> SyscallTable<NullAbi> for Kernel<'a> {
+            fn handle_call(&self, ctx: &mut (), num: Register, arg: EncodedType) -> EncodedType {
+                syscall_mounts! {
+                    number = num;
+                    args = arg;
+                    abi_type = NullAbi;
+                    abi = self.abi;
+                    context = ctx;
+                    prefix = |num| num >> 2;
+                    mounts = {
+                        (CoreNs, &self.core),
+                        (SubNs, &self.sub),
+                    }
+                }
+            }
+        }
+
+        let abi = Arc::new(NullAbi::default());
+        let kernel = Kernel {
+            abi: &abi,
+            core: NullHandler { abi: abi.clone() },
+            sub: SubTable::new(&abi, &SubKernel),
+        };
+
+        // NullHandler always answers Bar with false.
+        let ret = kernel.handle_call(&mut (), Bar::NUM, Bar { x: 1, y: 1 }.into());
+        assert!(!Baz::from(ret).a);
+
+        let call = |num: Register, args: Versioned| {
+            abi.with_alloc(core::alloc::Layout::new::<Versioned>(), |alloc| {
+                let mut encoder = abi.arg_encoder(alloc);
+                args.encode(&mut encoder).unwrap();
+                let num = abi.versioned_num(num, <Versioned as SyscallApi<NullAbi>>::VERSION);
+                let ret = kernel.handle_call(&mut (), num, encoder.finish());
+                let mut decoder = abi.ret_decoder(ret);
+                let ret: Result<u32, SyscallError<SimpleErr>> = decoder.decode().unwrap();
+                Result::<_, SyscallError<()>>::Ok(ret)
+            })
+            .unwrap()
+        };
+        let args = Versioned { x: 1, y: 2 };
+        assert_eq!(call(<Versioned as SyscallApi<NullAbi>>::NUM, args), Ok(3));
+        assert_eq!(call(9, args), Err(SyscallError::InvalidNum));
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_commands() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use syscall_encode_macros::SyscallCommands;
+
+        #[derive(SyscallEncodable, SyscallCommands, Clone, Copy, Debug)]
+        #[repr(C)]
+        #[command(abi = NullAbi, num = 7)]
+        pub enum DevCmd {
+            #[command(returns = u32, error = SimpleErr)]
+            Read(u32),
+            #[command(error = SimpleErr, name = reset_all)]
+            Reset,
+            #[command(returns = FooRet, error = ())]
+            GetFoo(Foo),
+        }
+
+        struct Dev {
+            value: AtomicU32,
+        }
+
+        impl DevCmdHandler for Dev {
+            fn read(&self, args: u32) -> Result<u32, SimpleErr> {
+                let value = self.value.load(Ordering::Relaxed);
+                value.checked_add(args).ok_or(SimpleErr::VerySad)
+            }
+
+            fn reset_all(&self) -> Result<(), SimpleErr> {
+                self.value.store(0, Ordering::Relaxed);
+                Ok(())
+            }
+
+            fn get_foo(&self, _args: Foo) -> Result<FooRet, ()> {
+                Ok(FooRet)
+            }
+        }
+
+        struct DevTable {
+            abi: Arc<NullAbi>,
+            dev: Dev,
+        }
+
+        impl SyscallTable<NullAbi> for DevTable {
+            fn handle_call(&self, _ctx: &mut (), num: Register, arg: EncodedType) -> EncodedType {
+                unsafe {
+                    syscall_api! {
+                        number = num;
+                        args = arg;
+                        abi_type = NullAbi;
+                        abi = &*self.abi;
+                        handlers = {
+                            (DevCmd, |_n, cmd: DevCmd| {
+                                cmd.dispatch(&self.dev)
+                            })
+                        }
+                        fast_handlers = {}
+                    }
+                }
+            }
+        }
+
+        let abi = Arc::new(NullAbi::default());
+        let table = DevTable {
+            abi: abi.clone(),
+            dev: Dev {
+                value: AtomicU32::new(10),
+            },
+        };
+        let thr = std::thread::spawn(move || {
+            for _ in 0..4 {
+                let (num, args) = table.abi.arg_receiver.lock().unwrap().recv().unwrap();
+                let ret = table.handle_call(&mut (), num, args);
+                table.abi.ret_sender.lock().unwrap().send(ret).unwrap();
+            }
+        });
+
+        assert_eq!(DevCmd::read(&abi, 5), Ok(15));
+        assert_eq!(
+            DevCmd::read(&abi, u32::MAX),
+            Err(SyscallError::SyscallError(SimpleErr::VerySad))
+        );
+        assert_eq!(DevCmd::reset_all(&abi), Ok(()));
+        assert_eq!(DevCmd::get_foo(&abi, Foo::default()), Ok(FooRet));
+        thr.join().unwrap();
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_restart() {
+        use syscall_encode_traits::{api::RestartPolicy, error::errno};
+
+        #[derive(SyscallEncodable, Clone, Copy, Debug)]
+        #[repr(C)]
+        pub struct Sleep {
+            ticks: u64,
+        }
+
+        impl<'a> SyscallApi<'a, NullAbi> for Sleep {
+            const NUM: Register = 11;
+            const RESTART_POLICY: RestartPolicy = RestartPolicy::RestartAdjusted;
+            type ReturnType = u64;
+            type ErrorType = SimpleErr;
+
+            fn restart_args(&self, state: u64) -> Option<Self> {
+                Some(Sleep { ticks: state })
+            }
+        }
+
+        #[derive(SyscallEncodable, Clone, Copy, Debug)]
+        #[repr(C)]
+        pub struct Wait {
+            ticks: u64,
+        }
+
+        impl<'a> SyscallApi<'a, NullAbi> for Wait {
+            const NUM: Register = 12;
+            const RESTART_POLICY: RestartPolicy = RestartPolicy::Restart;
+            const MAX_RESTARTS: u32 = 2;
+            type ReturnType = u64;
+            type ErrorType = SimpleErr;
+        }
+
+        // Interrupts the next few blocking calls halfway through, saving the ticks left to sleep.
+        struct SleepTable {
+            abi: Arc<NullAbi>,
+            interrupts: Mutex<u32>,
+        }
+
+        impl SleepTable {
+            fn sleep(&self, ticks: u64) -> Result<u64, SyscallError<SimpleErr>> {
+                let mut interrupts = self.interrupts.lock().unwrap();
+                match ticks {
+                    0 => Err(SyscallError::Interrupted),
+                    u64::MAX => Err(SyscallError::SyscallError(SimpleErr::Sad)),
+                    _ if *interrupts > 0 => {
+                        *interrupts -= 1;
+                        Err(SyscallError::Restart(ticks / 2))
+                    }
+                    _ => Ok(ticks),
+                }
+            }
+        }
+
+        impl SyscallTable<NullAbi> for SleepTable {
+            fn handle_call(&self, _ctx: &mut (), num: Register, arg: EncodedType) -> EncodedType {
+                unsafe {
+                    syscall_api! {
+                        number = num;
+                        args = arg;
+                        abi_type = NullAbi;
+                        abi = &*self.abi;
+                        handlers = {
+                            (Sleep, |_n, s: Sleep| self.sleep(s.ticks), restartable = true),
+                            (Wait, |_n, w: Wait| self.sleep(w.ticks), restartable = true),
+                            (Foo, |_n, _foo| {
+                                self.sleep(8).map(|_| FooRet)
+                            }, restartable = true)
+                        }
+                        fast_handlers = {}
+                    }
+                }
+            }
+        }
+
+        let abi = Arc::new(NullAbi::default());
+        let table = Arc::new(SleepTable {
+            abi: abi.clone(),
+            interrupts: Mutex::new(0),
+        });
+        let kernel = table.clone();
+        let thr = std::thread::spawn(move || {
+            for _ in 0..11 {
+                let (num, args) = kernel.abi.arg_receiver.lock().unwrap().recv().unwrap();
+                let ret = kernel.handle_call(&mut (), num, args);
+                kernel.abi.ret_sender.lock().unwrap().send(ret).unwrap();
+            }
+        });
+
+        // Restarted with the ticks left to sleep: 8, then 4, then 2.
+        *table.interrupts.lock().unwrap() = 2;
+        assert_eq!(Sleep { ticks: 8 }.perform_call(&abi), Ok(2));
+        // Restarted with the same arguments.
+        *table.interrupts.lock().unwrap() = 1;
+        assert_eq!(Wait { ticks: 8 }.perform_call(&abi), Ok(8));
+        // Returned to the caller after restarting MAX_RESTARTS times.
+        *table.interrupts.lock().unwrap() = 5;
+        assert_eq!(
+            Wait { ticks: 8 }.perform_call(&abi),
+            Err(SyscallError::Restart(4))
+        );
+        assert_eq!(*table.interrupts.lock().unwrap(), 2);
+        // Returned to the caller.
+        *table.interrupts.lock().unwrap() = 1;
+        assert_eq!(
+            Foo::default().perform_call(&abi),
+            Err(SyscallError::Restart(4))
+        );
+        // Never restarted.
+        assert_eq!(
+            Wait { ticks: 0 }.perform_call(&abi),
+            Err(SyscallError::Interrupted)
+        );
+        assert_eq!(
+            Wait { ticks: u64::MAX }.perform_call(&abi),
+            Err(SyscallError::SyscallError(SimpleErr::Sad))
+        );
+        thr.join().unwrap();
+
+        let e = SyscallError::<SimpleErr>::Interrupted;
+        assert_eq!(SyscallError::from_raw(e.into_raw()), Some(e));
+        assert_eq!(e.errno(), errno::EINTR);
+        assert_eq!(SyscallError::<SimpleErr>::Restart(4).errno(), errno::EINTR);
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_blocking() {
+        use syscall_encode_traits::table::{Blocking, BlockingSyscallTable};
+
+        struct BlockingKernel {
+            abi: Arc<NullAbi>,
+        }
+
+        // The context counts the times the call has blocked, and the wait object is a wait queue number.
+        impl BlockingSyscallTable<NullAbi, u32> for BlockingKernel {
+            type WaitObject = u32;
+
+            fn handle_call(
+                &self,
+                ctx: &mut u32,
+                num: Register,
+                arg: EncodedType,
+            ) -> Blocking<EncodedType, u32> {
+                unsafe {
+                    syscall_api! {
+                        number = num;
+                        args = arg;
+                        abi_type = NullAbi;
+                        abi = &*self.abi;
+                        context = ctx;
+                        mode = blocking;
+                        handlers = {
+                            (Versioned, |blocked: &mut u32, _n, v: Versioned| {
+                                if *blocked < 2 {
+                                    *blocked += 1;
+                                    Blocking::Block(v.x)
+                                } else {
+                                    Blocking::Ready(Ok(v.x + v.y))
+                                }
+                            })
+                        }
+                        fast_handlers = {
+                            (Bar, |_ctx, _n, b: Bar| {
+                                Baz { a: b.x == b.y }
+                            })
+                        }
+                    }
+                }
+            }
+        }
+
+        let abi = Arc::new(NullAbi::default());
+        let kernel = BlockingKernel { abi: abi.clone() };
+        let thr = std::thread::spawn(move || {
+            let mut waits = Vec::new();
+            for _ in 0..3 {
+                let (num, args) = kernel.abi.arg_receiver.lock().unwrap().recv().unwrap();
+                let mut blocked = 0;
+                let ret = loop {
+                    match kernel.handle_call(&mut blocked, num, args) {
+                        Blocking::Ready(ret) => break ret,
+                        Blocking::Block(queue) => waits.push(queue),
+                    }
+                };
+                kernel.abi.ret_sender.lock().unwrap().send(ret).unwrap();
+            }
+            waits
+        });
+
+        assert_eq!(Versioned { x: 1, y: 2 }.perform_call(&abi), Ok(3));
+        assert_eq!(
+            Foo::default().perform_call(&abi),
+            Err(SyscallError::InvalidNum)
+        );
+        assert!(Bar { x: 1, y: 1 }.perform_call(&abi).a);
+        assert_eq!(thr.join().unwrap(), vec![1, 1]);
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_async() {
+        use std::{
+            future::Future,
+            pin::{pin, Pin},
+            task::{Context, Poll, Waker},
+        };
+        use syscall_encode_traits::table::AsyncSyscallTable;
+
+        // Pending the first time it is polled.
+        struct YieldOnce(bool);
+
+        impl Future for YieldOnce {
+            type Output = ();
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+                if self.0 {
+                    return Poll::Ready(());
+                }
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+
+        struct AsyncKernel {
+            abi: Arc<NullAbi>,
+        }
+
+        // The context counts the calls that reached a handler.
+        impl AsyncSyscallTable<NullAbi, u32> for AsyncKernel {
+            fn handle_call<'t>(
+                &'t self,
+                ctx: &'t mut u32,
+                num: Register,
+                arg: EncodedType,
+            ) -> impl Future<Output = EncodedType> + 't {
+                unsafe {
+                    syscall_api! {
+                        number = num;
+                        args = arg;
+                        abi_type = NullAbi;
+                        abi = &*self.abi;
+                        context = ctx;
+                        mode = async;
+                        handlers = {
+                            (Versioned, |calls: &mut u32, _n, v: Versioned| {
+                                *calls += 1;
+                                async move {
+                                    YieldOnce(false).await;
+                                    Ok(v.x + v.y)
+                                }
+                            }, upgrades = [VersionedV1])
+                        }
+                        fast_handlers = {}
+                    }
+                }
+            }
+        }
+
+        let abi = Arc::new(NullAbi::default());
+        let kernel = AsyncKernel { abi: abi.clone() };
+        let thr = std::thread::spawn(move || {
+            let mut calls = 0;
+            let mut polls = Vec::new();
+            for _ in 0..2 {
+                let (num, args) = kernel.abi.arg_receiver.lock().unwrap().recv().unwrap();
+                let mut call = pin!(kernel.handle_call(&mut calls, num, args));
+                let mut cx = Context::from_waker(Waker::noop());
+                let mut n = 1;
+                let ret = loop {
+                    match call.as_mut().poll(&mut cx) {
+                        Poll::Ready(ret) => break ret,
+                        Poll::Pending => n += 1,
+                    }
+                };
+                polls.push(n);
+                kernel.abi.ret_sender.lock().unwrap().send(ret).unwrap();
+            }
+            (calls, polls)
+        });
+
+        assert_eq!(VersionedV1 { x: 1 }.perform_call(&abi), Ok(11));
+        assert_eq!(
+            Foo::default().perform_call(&abi),
+            Err(SyscallError::InvalidNum)
+        );
+        assert_eq!(thr.join().unwrap(), (1, vec![2, 1]));
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_spill() {
+        use syscall_encode_traits::spill::{ScratchBuffer, SpillAllocator};
+
+        thread_local! {
+            static SCRATCH: ScratchBuffer<256> = const { ScratchBuffer::new() };
+        }
+
+        let abi = Arc::new(NullAbi::default());
+        let handler = NullHandler { abi: abi.clone() };
+        let thr = std::thread::spawn(move || {
+            for _ in 0..2 {
+                let (num, args) = handler.abi.arg_receiver.lock().unwrap().recv().unwrap();
+                let ret = handler.handle_call(&mut (), num, args);
+                handler.abi.ret_sender.lock().unwrap().send(ret).unwrap();
+            }
+        });
+
+        let foo = Foo::default();
+        assert_eq!(foo.perform_call_in(&abi, &mut [0u8; 256]), Ok(FooRet));
+        match foo.perform_call_in(&abi, &mut [0u8; 8]) {
+            Err(SyscallError::BufferTooSmall(needed)) => assert!(needed >= size_of::<Foo>()),
+            res => panic!("expected BufferTooSmall, got {:?}", res),
+        }
+        SCRATCH.with(|mut scratch| {
+            assert_eq!(foo.perform_call_with(&abi, &mut scratch), Ok(FooRet));
+            assert!(!scratch.in_use());
+            // A call made while the buffer is in use fails, rather than overwriting the first call's spill.
+            let layout = std::alloc::Layout::new::<u64>();
+            let res = scratch.with_spill(layout, |_| {
+                assert_eq!(
+                    foo.perform_call_with(&abi, &mut &*scratch),
+                    Err(SyscallError::AllocationError)
+                );
+                Result::<_, SyscallError<()>>::Ok(())
+            });
+            assert_eq!(res, Ok(()));
+        });
+        thr.join().unwrap();
+
+        let e = SyscallError::<SimpleErr>::BufferTooSmall(64);
+        assert_eq!(
+            e.to_string(),
+            "syscall spill buffer is too small, needs 64 bytes"
+        );
+    }
+
+    #[test]
+    fn test_allocation() {
+        use syscall_encode_traits::abi::Allocation;
+
+        #[repr(align(8))]
+        struct Aligned([u8; 64]);
+
+        let mut buf = Aligned([0; 64]);
+        let mut alloc = Allocation::from(&mut buf.0[..]);
+        assert_eq!(alloc.remaining(), 64);
+
+        alloc.reserve_bytes(3, 1).unwrap().copy_from_slice(b"abc");
+        assert_eq!(alloc.used(), 3);
+        assert!(alloc.reserve_bytes(1, 3).is_none());
+
+        let mark = alloc.mark();
+        let words = alloc.reserve_slice::<u32>(4).unwrap();
+        assert_eq!(words.as_ptr() as usize % 4, 0);
+        for (i, w) in words.iter_mut().enumerate() {
+            w.write(i as u32);
+        }
+        assert_eq!(alloc.used(), 20);
+        assert!(alloc.reserve_slice::<u64>(6).is_none());
+        assert_eq!(alloc.used(), 20);
+
+        let later = alloc.mark();
+        alloc.rollback(mark);
+        assert_eq!(alloc.used(), 3);
+        // Marks past the current point don't undo anything.
+        alloc.rollback(later);
+        assert_eq!(alloc.used(), 3);
+        *alloc.reserve::<u8>().unwrap() = b'd';
+        assert_eq!(alloc.remaining(), 60);
+        assert!(Allocation::null().reserve::<u8>().is_none());
+        assert_eq!(&buf.0[..4], b"abcd");
+        assert_eq!(buf.0[8..12], 1u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn test_spill_strategies() {
+        use std::alloc::Layout;
+        use syscall_encode_traits::spill::{BumpArena, SharedPage, SpillAllocator, StackSpill};
+
+        fn fill<S: SpillAllocator + ?Sized>(
+            spill: &mut S,
+            layout: Layout,
+        ) -> Result<usize, SyscallError<()>> {
+            spill.with_spill(layout, |mut alloc| {
+                let bytes = alloc.reserve_bytes(layout.size(), layout.align()).unwrap();
+                bytes.fill(0xaa);
+                Ok(bytes.as_ptr() as usize)
+            })
+        }
+
+        let layout = Layout::new::<[u64; 4]>();
+        assert!(fill(&mut StackSpill::<64>, layout).is_ok());
+        assert_eq!(
+            fill(&mut StackSpill::<16>, layout),
+            Err(SyscallError::BufferTooSmall(32))
+        );
+
+        // Nested calls get their own memory from the arena, which is freed as they return.
+        let mut arena = BumpArena::<64>::new();
+        let res = (&arena).with_spill(layout, |_| {
+            assert_eq!(arena.used(), 32);
+            assert_eq!(fill(&mut &arena, layout).map(|_| arena.used()), Ok(32));
+            assert_eq!(
+                fill(&mut &arena, Layout::new::<[u64; 5]>()),
+                Err(SyscallError::BufferTooSmall(72))
+            );
+            Result::<_, SyscallError<()>>::Ok(())
+        });
+        assert_eq!(res, Ok(()));
+        assert_eq!(arena.used(), 0);
+        assert!(fill(&mut arena, Layout::new::<[u64; 8]>()).is_ok());
+
+        let mut page = [0u64; 8];
+        let shared = unsafe { SharedPage::new(page.as_mut_ptr() as *mut u8, 64) };
+        assert_eq!(fill(&mut &shared, layout), Ok(shared.base() as usize));
+        let res = (&shared).with_spill(layout, |_| {
+            assert!(shared.in_use());
+            assert_eq!(
+                fill(&mut &shared, layout),
+                Err(SyscallError::AllocationError)
+            );
+            Result::<_, SyscallError<()>>::Ok(())
+        });
+        assert_eq!(res, Ok(()));
+        assert!(!shared.in_use());
+        assert_eq!(page[..4], [0xaaaaaaaaaaaaaaaa; 4]);
+
+        #[cfg(feature = "alloc")]
+        {
+            use syscall_encode_traits::spill::HeapSpill;
+            assert!(fill(&mut HeapSpill, layout).is_ok());
+            assert!(fill(&mut HeapSpill, Layout::new::<()>()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_unique_nums() {
+        use syscall_encode_traits::assert_unique_syscall_nums;
+
+        // Versions of a syscall share a NUM, but only the current version is registered.
+        assert_unique_syscall_nums! {
+            abi_type = NullAbi;
+            handlers = { Foo, Versioned }
+            fast_handlers = { Bar }
+        }
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        use syscall_encode_traits::fingerprint::AbiHash;
+
+        #[derive(SyscallEncodable, Clone, Copy, Debug)]
+        #[repr(C)]
+        pub struct Swapped {
+            y: u32,
+            x: u32,
+        }
+
+        #[derive(SyscallEncodable, Clone, Copy, Debug)]
+        #[repr(C)]
+        pub struct Widened {
+            x: u64,
+            y: u32,
+        }
+
+        assert_ne!(Versioned::ABI_HASH, Swapped::ABI_HASH);
+        assert_ne!(Versioned::ABI_HASH, Widened::ABI_HASH);
+        // Swapped has the same size as Versioned, but pointers to it are still told apart.
+        assert_ne!(
+            UserPointer::<Versioned>::ABI_HASH,
+            UserPointer::<Swapped>::ABI_HASH
+        );
+        assert_ne!(UserSlice::<u32>::ABI_HASH, UserSlice::<i32>::ABI_HASH);
+        assert_ne!(
+            <Versioned as SyscallApi<NullAbi>>::FINGERPRINT,
+            <VersionedV1 as SyscallApi<NullAbi>>::FINGERPRINT
+        );
+        let raw = SyscallError::<SimpleErr>::AbiMismatch.into_raw();
+        assert_eq!(
+            SyscallError::from_raw(raw),
+            Some(SyscallError::<SimpleErr>::AbiMismatch)
+        );
+    }
+
+    #[derive(SyscallEncodable, Clone, Copy, Debug, PartialEq, Eq)]
+    #[repr(C)]
+    #[syscall(extensible)]
+    pub struct OpenHowV0 {
+        flags: u64,
+    }
+
+    #[derive(SyscallEncodable, Clone, Copy, Debug, PartialEq, Eq)]
+    #[repr(C)]
+    #[syscall(extensible)]
+    pub struct OpenHow {
+        flags: u64,
+        #[syscall(since = 1, default = 0o644)]
+        mode: u64,
+        #[syscall(since = 2)]
+        resolve: u64,
+    }
+
+    #[derive(SyscallEncodable, Clone, Copy, Debug, PartialEq, Eq)]
+    #[repr(C)]
+    #[syscall(extensible)]
+    pub struct OpenHowV3 {
+        flags: u64,
+        #[syscall(since = 1)]
+        mode: u64,
+        #[syscall(since = 2)]
+        resolve: u64,
+        #[syscall(since = 3)]
+        extra: u64,
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_extensible() {
+        use syscall_encode_traits::extensible::{
+            ExtensibleError, ExtensibleStruct, UserExtensible,
+        };
+
+        assert_eq!(OpenHow::MIN_SIZE, 8);
+        assert_eq!(OpenHow::VERSION, 2);
+
+        let abi = Arc::new(NullAbi::default());
+        let old = OpenHowV0 { flags: 1 };
+        let arg: UserExtensible<OpenHow> = test_reencode(&abi, UserExtensible::new(&old)).unwrap();
+        let how = arg.copy_in(|_, _| true).unwrap();
+        assert_eq!(
+            how,
+            OpenHow {
+                flags: 1,
+                mode: 0o644,
+                resolve: 0
+            }
+        );
+
+        let current = OpenHow {
+            flags: 2,
+            mode: 0o600,
+            resolve: 3,
+        };
+        let arg: UserExtensible<OpenHow> =
+            test_reencode(&abi, UserExtensible::new(&current)).unwrap();
+        assert_eq!(arg.copy_in(|_, _| true), Ok(current));
+
+        let mut new = OpenHowV3 {
+            flags: 2,
+            mode: 0o600,
+            resolve: 3,
+            extra: 0,
+        };
+        let arg: UserExtensible<OpenHow> = test_reencode(&abi, UserExtensible::new(&new)).unwrap();
+        assert_eq!(arg.copy_in(|_, _| true), Ok(current));
+        new.extra = 5;
+        let arg: UserExtensible<OpenHow> = test_reencode(&abi, UserExtensible::new(&new)).unwrap();
+        assert_eq!(
+            arg.copy_in(|_, _| true),
+            Err(ExtensibleError::UnknownFields)
+        );
+        assert_eq!(
+            arg.copy_in(|_, _| false),
+            Err(ExtensibleError::InvalidPointer)
+        );
+
+        let short = UserExtensible::<OpenHow>::from_parts(UserPointer::new(&0u8), 4);
+        assert_eq!(short.copy_in(|_, _| true), Err(ExtensibleError::TooSmall));
+    }
+
+    #[derive(SyscallEncodable, Clone, Copy, Debug, PartialEq, Eq)]
+    #[repr(C)]
+    pub struct Outer {
+        a: u16,
+        opts: FooOpts,
+    }
+
+    #[derive(SyscallEncodable, Clone, Copy, Debug, PartialEq, Eq)]
+    #[repr(C)]
+    pub struct RawOuter {
+        a: u16,
+        disc: u64,
+        x: u32,
+        b: u8,
+    }
+
+    #[cfg(not(feature = "compact-errors"))]
+    #[test]
+    fn test_decode_context() {
+        use syscall_encode_traits::encoder::context::DecodeContext;
+
+        fn decode_outer(abi: &NullAbi, raw: RawOuter) -> (DecodeError, DecodeContext) {
+            let layout = core::alloc::Layout::new::<RawOuter>();
+            abi.with_alloc(layout, |alloc| {
+                let mut encoder = abi.arg_encoder(alloc);
+                raw.encode(&mut encoder).unwrap();
+                let mut decoder = abi.arg_decoder(encoder.finish());
+                let err = Outer::decode(&mut decoder).unwrap_err();
+                Result::<_, SyscallError<()>>::Ok((err, decoder.decode_context().unwrap()))
+            })
+            .unwrap()
+        }
+
+        let abi = NullAbi::default();
+        let raw = RawOuter {
+            a: 1,
+            disc: 7,
+            x: 0,
+            b: 0,
+        };
+        let (err, context) = decode_outer(&abi, raw);
+        assert_eq!(err, DecodeError::BadDiscriminant);
+        assert_eq!(context.error(), DecodeError::BadDiscriminant);
+        assert_eq!(context.offset(), Some(10));
+        assert_eq!(context.path().iter().collect::<Vec<_>>(), ["opts"]);
+
+        let raw = RawOuter {
+            disc: 1,
+            b: 2,
+            ..raw
+        };
+        let (err, context) = decode_outer(&abi, raw);
+        assert_eq!(err, DecodeError::BadBool);
+        assert_eq!(context.offset(), Some(15));
+        assert_eq!(context.path().to_string(), "opts.B.1");
+        assert_eq!(context.to_string(), "bad bool in field opts.B.1 at byte 15");
+        let context = context.with_num(7u64);
+        assert_eq!(context.num(), 7);
+        assert_eq!(context.error(), DecodeError::BadBool);
+        let e = SyscallError::<SimpleErr>::from(err);
+        assert_eq!(e, SyscallError::DecodeFailed(DecodeError::BadBool));
+        assert_eq!(SyscallError::from_raw(e.into_raw()), Some(e));
+        assert_eq!(e.to_string(), "failed to decode syscall data: bad bool");
+        test_encode(&Arc::new(abi), e, |_, _| {});
+    }
+
+    #[cfg(not(miri))]
+    #[test]
+    fn test_object_pointer() {
+        use syscall_encode_traits::{encoder::EncodeError, ptr::ObjectPointer};
+
+        let abi = Arc::new(NullAbi::default());
+        let lookup = |id| {
+            (id == TEST_OBJECT_ID)
+                .then(|| (TEST_OBJECT.as_ptr() as *const u8, size_of_val(&TEST_OBJECT)))
+        };
+        let ptr = ObjectPointer::from_local(&TEST_OBJECT[2]);
+        let decoded: ObjectPointer<u32> = test_reencode(&abi, ptr).unwrap();
+        assert_eq!(decoded.id(), Some(TEST_OBJECT_ID));
+        assert_eq!(decoded.offset(), Some(8));
+        assert_eq!(decoded.resolve(lookup), Some(&3));
+        assert_eq!(decoded.to_local(&*abi), Some(&TEST_OBJECT[2] as *const u32));
+
+        let oob = ObjectPointer::<u32>::new(TEST_OBJECT_ID, 14);
+        assert_eq!(oob.resolve(lookup), None);
+        let unknown = ObjectPointer::<u32>::new(7, 0);
+        assert_eq!(unknown.resolve(lookup), None);
+
+        let mut object = [0u32; 4];
+        let local = ObjectPointer::from_local(&object[0]);
+        let res = abi.with_alloc(core::alloc::Layout::new::<ObjectPointer<u32>>(), |alloc| {
+            let mut encoder = abi.arg_encoder(alloc);
+            Result::<_, SyscallError<()>>::Ok(local.encode(&mut encoder))
+        });
+        assert_eq!(res, Ok(Err(EncodeError::UntranslatableAddress)));
+
+        let base = object.as_mut_ptr() as *mut u8;
+        let mut ptr = ObjectPointer::<u32>::new(TEST_OBJECT_ID, 4);
+        *ptr.resolve_mut(|_| Some((base, 16))).unwrap() = 9;
+        assert_eq!(object, [0, 9, 0, 0]);
+    }
+
+    #[cfg(feature = "handle-table")]
+    #[test]
+    fn test_handles() {
+        use syscall_encode_traits::{
+            fingerprint::{hash_str, AbiHash, HASH_SEED},
+            handle::{Handle, HandleError, HandleTable},
+        };
+
+        struct File;
+        impl AbiHash for File {
+            const ABI_HASH: u64 = hash_str(HASH_SEED, "File");
+        }
+        struct Socket;
+        impl AbiHash for Socket {
+            const ABI_HASH: u64 = hash_str(HASH_SEED, "Socket");
+        }
+        assert_ne!(Handle::<File>::ABI_HASH, Handle::<Socket>::ABI_HASH);
+
+        let abi = Arc::new(NullAbi::default());
+        let mut table = HandleTable::<File, u32, 2>::new();
+        let a = table.insert(10).unwrap();
+        let b = table.insert(20).unwrap();
+        assert_eq!(table.insert(30), Err(30));
+        test_encode(&abi, a, |_, decoded| {
+            assert_eq!(table.get(decoded), Ok(&10));
+        });
+
+        assert_eq!(table.revoke(a), Ok(10));
+        assert_eq!(table.get(a), Err(HandleError::Stale));
+        let c = table.insert(30).unwrap();
+        assert_eq!(c.index(), a.index());
+        assert_eq!(table.get(a), Err(HandleError::Stale));
+        assert_eq!(table.get(c), Ok(&30));
+        assert_eq!(table.get(b), Ok(&20));
+        assert_eq!(table.get(Handle::new(5, 1)), Err(HandleError::Invalid));
+    }
+
+    #[cfg(feature = "bitflags")]
+    #[test]
+    fn test_bitflags() {
+        bitflags::bitflags! {
+            #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+            struct Prot: u32 {
+                const READ = 1;
+                const WRITE = 2;
+            }
+        }
+        syscall_encode_traits::bitflags_encodable!(Prot);
+
+        let abi = Arc::new(NullAbi::default());
+        test_encode(&abi, Prot::READ | Prot::WRITE, |_, _| {});
+        let res: Result<Prot, _> = test_reencode(&abi, 0x4u32);
+        assert_eq!(res, Err(DecodeError::InvalidData));
+    }
 }
@@ -0,0 +1,69 @@
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use syn::{Data, Fields, Generics};
+
+fn hash_fields(fields: &Fields) -> Vec<TokenStream> {
+    fields
+        .iter()
+        .map(|field| {
+            let ty = &field.ty;
+            let name = field.ident.as_ref().map(|name| {
+                let name = name.to_string();
+                quote! {let hash = ::syscall_encode_traits::fingerprint::hash_str(hash, #name);}
+            });
+            quote! {
+                #name
+                let hash = ::syscall_encode_traits::fingerprint::hash_u64(hash, <#ty as ::syscall_encode_traits::fingerprint::AbiHash>::ABI_HASH);
+            }
+        })
+        .collect()
+}
+
+pub fn derive_abi_hash(
+    ident: &Ident,
+    generics: &Generics,
+    data: &Data,
+    non_exhaustive: bool,
+) -> syn::Result<TokenStream> {
+    let (kind, body) = match data {
+        Data::Struct(st) => ("struct", hash_fields(&st.fields)),
+        // Variants are hashed in discriminant order, matching the encoding.
+        Data::Enum(en) => (
+            "enum",
+            en.variants
+                .iter()
+                .filter(|var| !(non_exhaustive && var.ident == "Unknown"))
+                .enumerate()
+                .map(|(num, var)| {
+                    let num = num as u64;
+                    let name = var.ident.to_string();
+                    let fields = hash_fields(&var.fields);
+                    quote! {
+                        let hash = ::syscall_encode_traits::fingerprint::hash_u64(hash, #num);
+                        let hash = ::syscall_encode_traits::fingerprint::hash_str(hash, #name);
+                        #(#fields)*
+                    }
+                })
+                .collect(),
+        ),
+        Data::Union(_) => return Ok(TokenStream::new()),
+    };
+
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(syn::parse_quote!(
+            ::syscall_encode_traits::fingerprint::AbiHash
+        ));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::syscall_encode_traits::fingerprint::AbiHash for #ident #ty_generics #where_clause {
+            const ABI_HASH: u64 = {
+                let hash = ::syscall_encode_traits::fingerprint::hash_str(::syscall_encode_traits::fingerprint::HASH_SEED, #kind);
+                #(#body)*
+                hash
+            };
+        }
+    })
+}
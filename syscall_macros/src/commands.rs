@@ -0,0 +1,231 @@
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote, spanned::Spanned};
+use syn::{parse_quote, DeriveInput, Expr, Fields, Type};
+
+struct Command {
+    variant: Ident,
+    method: Ident,
+    args: Option<Type>,
+    returns: Type,
+    error: Type,
+}
+
+fn snake_case(ident: &Ident) -> Ident {
+    let mut name = String::new();
+    for (i, c) in ident.to_string().chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                name.push('_');
+            }
+            name.extend(c.to_lowercase());
+        } else {
+            name.push(c);
+        }
+    }
+    Ident::new(&name, ident.span())
+}
+
+fn extract_command(variant: &syn::Variant) -> syn::Result<Command> {
+    let mut returns = None;
+    let mut error = None;
+    let mut method = None;
+    for attr in variant
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("command"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("returns") {
+                returns = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("error") {
+                error = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("name") {
+                method = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown command option."))
+            }
+        })?;
+    }
+
+    let args = match &variant.fields {
+        Fields::Unit => None,
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => Some(fields.unnamed[0].ty.clone()),
+        _ => {
+            return Err(syn::Error::new(
+                variant.fields.__span(),
+                "commands must have no fields, or a single unnamed field holding their arguments.",
+            ))
+        }
+    };
+    Ok(Command {
+        method: method.unwrap_or_else(|| snake_case(&variant.ident)),
+        variant: variant.ident.clone(),
+        args,
+        returns: returns.unwrap_or_else(|| parse_quote!(())),
+        error: error.ok_or_else(|| {
+            syn::Error::new(
+                variant.__span(),
+                "commands require a #[command(error = Type)] attribute.",
+            )
+        })?,
+    })
+}
+
+pub fn derive_proc_macro_impl(input: DeriveInput) -> syn::Result<TokenStream> {
+    let span = input.__span();
+    let syn::Data::Enum(en) = &input.data else {
+        return Err(syn::Error::new(
+            span,
+            "SyscallCommands is only supported on enums.",
+        ));
+    };
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.__span(),
+            "command enums cannot be generic.",
+        ));
+    }
+
+    let mut abi: Option<Type> = None;
+    let mut num: Option<Expr> = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("command")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("abi") {
+                abi = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("num") {
+                num = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown command option."))
+            }
+        })?;
+    }
+    let (Some(abi), Some(num)) = (abi, num) else {
+        return Err(syn::Error::new(
+            span,
+            "SyscallCommands requires a #[command(abi = Type, num = expr)] attribute.",
+        ));
+    };
+    let commands = en
+        .variants
+        .iter()
+        .map(extract_command)
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let ident = &input.ident;
+    let vis = &input.vis;
+    let ret_ident = format_ident!("{}Return", ident);
+    let err_ident = format_ident!("{}Error", ident);
+    let handler_ident = format_ident!("{}Handler", ident);
+    let variants: Vec<_> = commands.iter().map(|c| &c.variant).collect();
+    let returns: Vec<_> = commands.iter().map(|c| &c.returns).collect();
+    let errors: Vec<_> = commands.iter().map(|c| &c.error).collect();
+
+    // The return and error types get their encodings from the SyscallEncodable derive.
+    let ret_def: DeriveInput = parse_quote! {
+        #[repr(C)]
+        #vis enum #ret_ident {
+            #(#variants(#returns)),*
+        }
+    };
+    let err_def: DeriveInput = parse_quote! {
+        #[repr(C)]
+        #vis enum #err_ident {
+            #(#variants(#errors)),*
+        }
+    };
+    let ret_impl = crate::syscall::derive_proc_macro_impl(ret_def.clone())?;
+    let err_impl = crate::syscall::derive_proc_macro_impl(err_def.clone())?;
+    let ret_doc = format!("The result of a successful [{}] command.", ident);
+    let err_doc = format!("The error returned by a [{}] command.", ident);
+    let handler_doc = format!(
+        "Kernel-side handlers for each [{}] command. See [{}::dispatch]. Handlers take &self, like the handlers \
+         of a syscall table, since calls may run concurrently.",
+        ident, ident
+    );
+
+    let mut methods = Vec::new();
+    let mut handler_methods = Vec::new();
+    let mut dispatch_arms = Vec::new();
+    for Command {
+        variant,
+        method,
+        args,
+        returns,
+        error,
+    } in &commands
+    {
+        let (param, value, arg) = match args {
+            Some(ty) => (
+                Some(quote! {, args: #ty}),
+                quote! {Self::#variant(args)},
+                Some(quote! {args}),
+            ),
+            None => (None, quote! {Self::#variant}, None),
+        };
+        let doc = format!("Perform the {} command.", variant);
+        methods.push(quote! {
+            #[doc = #doc]
+            #vis fn #method(abi: &#abi #param) -> Result<#returns, ::syscall_encode_traits::error::SyscallError<#error>> {
+                use ::syscall_encode_traits::{api::SyscallApi, error::SyscallError};
+                #[allow(unreachable_patterns)]
+                match #value.perform_call(abi) {
+                    Ok(#ret_ident::#variant(ret)) => Ok(ret),
+                    // The kernel answered with another command's result.
+                    Ok(_) => Err(SyscallError::InvalidData),
+                    Err(e) => Err(e.map_syscall_error(|e| match e {
+                        #err_ident::#variant(e) => SyscallError::SyscallError(e),
+                        _ => SyscallError::InvalidData,
+                    })),
+                }
+            }
+        });
+        let doc = format!("Handle the {} command.", variant);
+        handler_methods.push(quote! {
+            #[doc = #doc]
+            fn #method(&self #param) -> Result<#returns, #error>;
+        });
+        dispatch_arms.push(quote! {
+            #value => handler.#method(#arg).map(#ret_ident::#variant).map_err(#err_ident::#variant),
+        });
+    }
+
+    Ok(quote! {
+        #[doc = #ret_doc]
+        #[derive(Clone, Copy)]
+        #ret_def
+
+        #[doc = #err_doc]
+        #[derive(Clone, Copy)]
+        #err_def
+
+        #ret_impl
+        #err_impl
+
+        #[doc = #handler_doc]
+        #vis trait #handler_ident {
+            #(#handler_methods)*
+        }
+
+        impl<'a> ::syscall_encode_traits::api::SyscallApi<'a, #abi> for #ident {
+            const NUM: <#abi as ::syscall_encode_traits::abi::SyscallAbi>::SyscallNumType = #num;
+            type ReturnType = #ret_ident;
+            type ErrorType = #err_ident;
+        }
+
+        impl #ident {
+            #(#methods)*
+
+            /// Handle this command with the matching method of handler, for use in a syscall table.
+            #vis fn dispatch(self, handler: &(impl #handler_ident + ?Sized)) -> Result<#ret_ident, #err_ident> {
+                match self {
+                    #(#dispatch_arms)*
+                }
+            }
+        }
+    })
+}
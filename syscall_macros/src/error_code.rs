@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use proc_macro2::{Ident, TokenStream};
+use quote::{quote, spanned::Spanned};
+use syn::{parse_quote, Attribute, DeriveInput, Expr, Lit};
+
+struct VariantInfo {
+    code: u32,
+    message: Option<String>,
+    errno: Option<Expr>,
+}
+
+fn extract_variant_attrs(span: proc_macro2::Span, attrs: &[Attribute]) -> syn::Result<VariantInfo> {
+    let mut code = None;
+    let mut message = None;
+    let mut errno = None;
+    for attr in attrs {
+        let Some(name) = attr.path().get_ident() else {
+            continue;
+        };
+        match name.to_string().as_str() {
+            "code" => {
+                let value = &attr.meta.require_name_value()?.value;
+                code = Some(match value {
+                    Expr::Lit(lit) => match &lit.lit {
+                        Lit::Int(i) => i.base10_parse::<u32>()?,
+                        _ => {
+                            return Err(syn::Error::new(
+                                value.__span(),
+                                "code must be an integer literal.",
+                            ))
+                        }
+                    },
+                    _ => {
+                        return Err(syn::Error::new(
+                            value.__span(),
+                            "code must be an integer literal.",
+                        ))
+                    }
+                });
+            }
+            "message" => {
+                let value = &attr.meta.require_name_value()?.value;
+                message = Some(match value {
+                    Expr::Lit(lit) => match &lit.lit {
+                        Lit::Str(s) => s.value(),
+                        _ => {
+                            return Err(syn::Error::new(
+                                value.__span(),
+                                "message must be a string literal.",
+                            ))
+                        }
+                    },
+                    _ => {
+                        return Err(syn::Error::new(
+                            value.__span(),
+                            "message must be a string literal.",
+                        ))
+                    }
+                });
+            }
+            "errno" => {
+                errno = Some(match &attr.meta {
+                    syn::Meta::List(list) => {
+                        let name: Ident = list.parse_args()?;
+                        parse_quote!(::syscall_encode_traits::error::errno::#name)
+                    }
+                    meta => meta.require_name_value()?.value.clone(),
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(VariantInfo {
+        code: code.ok_or_else(|| {
+            syn::Error::new(
+                span,
+                "ErrorCode requires a #[code = N] attribute on every variant.",
+            )
+        })?,
+        message,
+        errno,
+    })
+}
+
+pub fn derive_proc_macro_impl(input: DeriveInput) -> Result<TokenStream, syn::Error> {
+    let span = input.__span();
+    let DeriveInput {
+        ident: enum_name_ident,
+        data,
+        generics,
+        attrs,
+        ..
+    } = input;
+
+    let mut non_exhaustive = false;
+    for attr in &attrs {
+        if attr.path().is_ident("syscall") {
+            let opt: Ident = attr.meta.require_list()?.parse_args()?;
+            non_exhaustive |= opt == "non_exhaustive";
+        }
+    }
+
+    let syn::Data::Enum(en) = data else {
+        return Err(syn::Error::new(
+            span,
+            "ErrorCode can only be derived for enums.",
+        ));
+    };
+
+    let mut seen = HashMap::new();
+    let mut code_arms = Vec::new();
+    let mut from_code_arms = Vec::new();
+    let mut message_arms = Vec::new();
+    let mut errno_arms = Vec::new();
+    for var in &en.variants {
+        // The catch-all variant of a non_exhaustive enum has no code of its own.
+        if non_exhaustive && var.ident == "Unknown" {
+            code_arms
+                .push(quote! {Self::Unknown(_) => ::syscall_encode_traits::error::UNKNOWN_CODE,});
+            message_arms.push(quote! {Self::Unknown(_) => "unknown error",});
+            errno_arms.push(quote! {Self::Unknown(_) => None,});
+            continue;
+        }
+        if !matches!(var.fields, syn::Fields::Unit) {
+            return Err(syn::Error::new(
+                var.__span(),
+                "ErrorCode variants cannot have fields.",
+            ));
+        }
+        let info = extract_variant_attrs(var.__span(), &var.attrs)?;
+        if info.code == u32::MAX {
+            return Err(syn::Error::new(
+                var.__span(),
+                "code 4294967295 is reserved for unknown errors.",
+            ));
+        }
+        if let Some(other) = seen.insert(info.code, var.ident.clone()) {
+            return Err(syn::Error::new(
+                var.__span(),
+                format!("code {} is already used by variant {}.", info.code, other),
+            ));
+        }
+        let name = &var.ident;
+        let code = info.code;
+        let message = info.message.unwrap_or_else(|| name.to_string());
+        code_arms.push(quote! {Self::#name => #code,});
+        from_code_arms.push(quote! {#code => Some(Self::#name),});
+        message_arms.push(quote! {Self::#name => #message,});
+        errno_arms.push(match info.errno {
+            Some(errno) => quote! {Self::#name => Some(#errno),},
+            None => quote! {Self::#name => None,},
+        });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::syscall_encode_traits::error::ErrorCode for #enum_name_ident #ty_generics #where_clause {
+            fn code(&self) -> u32 {
+                match *self {#(#code_arms)*}
+            }
+
+            fn from_code(code: u32) -> Option<Self> {
+                match code {
+                    #(#from_code_arms)*
+                    _ => None,
+                }
+            }
+
+            fn message(&self) -> &'static str {
+                match *self {#(#message_arms)*}
+            }
+
+            fn errno(&self) -> Option<i32> {
+                match *self {#(#errno_arms)*}
+            }
+        }
+    })
+}
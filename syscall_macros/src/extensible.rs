@@ -0,0 +1,118 @@
+use proc_macro2::{Ident, TokenStream};
+use quote::{quote, spanned::Spanned};
+use syn::{DataStruct, Expr, Generics, LitInt};
+
+struct FieldVersion {
+    since: u32,
+    default: Option<Expr>,
+}
+
+fn extract_field_attrs(field: &syn::Field) -> syn::Result<FieldVersion> {
+    let mut since = 0;
+    let mut default = None;
+    for attr in &field.attrs {
+        if !attr.path().is_ident("syscall") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("since") {
+                since = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                Ok(())
+            } else if meta.path.is_ident("default") {
+                default = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown extensible field option."))
+            }
+        })?;
+    }
+    Ok(FieldVersion { since, default })
+}
+
+pub fn derive_extensible(
+    ident: &Ident,
+    generics: &Generics,
+    st: &DataStruct,
+) -> syn::Result<TokenStream> {
+    if !generics.params.is_empty() {
+        return Err(syn::Error::new(
+            generics.__span(),
+            "extensible structs cannot be generic.",
+        ));
+    }
+    let syn::Fields::Named(fields) = &st.fields else {
+        return Err(syn::Error::new(
+            st.fields.__span(),
+            "extensible structs must have named fields.",
+        ));
+    };
+
+    let mut version = 0;
+    let mut min_size = Vec::new();
+    let mut defaults = Vec::new();
+    let mut types = Vec::new();
+    for field in &fields.named {
+        let info = extract_field_attrs(field)?;
+        if info.since < version {
+            return Err(syn::Error::new(
+                field.__span(),
+                "extensible fields must be added in version order.",
+            ));
+        }
+        version = info.since;
+
+        let name = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let end = quote! {core::mem::offset_of!(Self, #name) + core::mem::size_of::<#ty>()};
+        if info.since == 0 {
+            if info.default.is_some() {
+                return Err(syn::Error::new(
+                    field.__span(),
+                    "fields of the first version of an extensible struct cannot have defaults.",
+                ));
+            }
+            min_size.push(quote! {
+                let end = #end;
+                if end > min {
+                    min = end;
+                }
+            });
+        } else {
+            let default = info
+                .default
+                .unwrap_or_else(|| syn::parse_quote!(core::default::Default::default()));
+            defaults.push(quote! {
+                if size < #end {
+                    self.#name = #default;
+                }
+            });
+        }
+        types.push(ty);
+    }
+
+    let fill_defaults = if defaults.is_empty() {
+        quote! {fn fill_defaults(&mut self, _size: usize) {}}
+    } else {
+        quote! {
+            fn fill_defaults(&mut self, size: usize) {
+                #(#defaults)*
+            }
+        }
+    };
+
+    Ok(quote! {
+        unsafe impl ::syscall_encode_traits::extensible::ExtensibleStruct for #ident
+        where
+            #(#types: ::syscall_encode_traits::extensible::Plain,)*
+        {
+            const MIN_SIZE: usize = {
+                let mut min = 0;
+                #(#min_size)*
+                min
+            };
+            const VERSION: u32 = #version;
+
+            #fill_defaults
+        }
+    })
+}
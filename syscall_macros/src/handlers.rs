@@ -0,0 +1,176 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, spanned::Spanned};
+use syn::{punctuated::Punctuated, FnArg, ItemTrait, Token, TraitItem, TraitItemFn, Type};
+
+struct Handler {
+    method: syn::Ident,
+    ty: Type,
+    fast: bool,
+    restartable: bool,
+    upgrades: Vec<Type>,
+}
+
+fn extract_handler(method: &mut TraitItemFn, context: bool) -> syn::Result<Handler> {
+    let mut fast = false;
+    let mut restartable = false;
+    let mut upgrades = Vec::new();
+    for attr in method.attrs.iter().filter(|a| a.path().is_ident("syscall")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("fast") {
+                fast = true;
+                Ok(())
+            } else if meta.path.is_ident("restartable") {
+                restartable = true;
+                Ok(())
+            } else if meta.path.is_ident("upgrades") {
+                let value = meta.value()?;
+                let content;
+                syn::bracketed!(content in value);
+                upgrades.extend(Punctuated::<Type, Token![,]>::parse_terminated(&content)?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown syscall handler option."))
+            }
+        })?;
+    }
+    method.attrs.retain(|a| !a.path().is_ident("syscall"));
+
+    if fast && !upgrades.is_empty() {
+        return Err(syn::Error::new(
+            method.sig.__span(),
+            "fast syscalls cannot have upgrades.",
+        ));
+    }
+    if fast && restartable {
+        return Err(syn::Error::new(
+            method.sig.__span(),
+            "fast syscalls cannot be restartable.",
+        ));
+    }
+    if method.default.is_some() {
+        return Err(syn::Error::new(
+            method.default.__span(),
+            "syscall handlers cannot have default implementations.",
+        ));
+    }
+    let mut inputs = method.sig.inputs.iter();
+    let recv = inputs.next();
+    if context && !matches!(inputs.next(), Some(FnArg::Typed(_))) {
+        return Err(syn::Error::new(
+            method.sig.__span(),
+            "syscall handlers must take &self, the context, and the syscall's arguments.",
+        ));
+    }
+    let (Some(FnArg::Receiver(recv)), Some(FnArg::Typed(arg)), None) =
+        (recv, inputs.next(), inputs.next())
+    else {
+        return Err(syn::Error::new(
+            method.sig.__span(),
+            "syscall handlers must take &self and the syscall's arguments.",
+        ));
+    };
+    if recv.reference.is_none() || recv.mutability.is_some() {
+        return Err(syn::Error::new(
+            recv.__span(),
+            "syscall handlers must take &self.",
+        ));
+    }
+    Ok(Handler {
+        method: method.sig.ident.clone(),
+        ty: (*arg.ty).clone(),
+        fast,
+        restartable,
+        upgrades,
+    })
+}
+
+pub fn syscall_handlers_impl(
+    abi: Type,
+    context: Option<Type>,
+    mut item: ItemTrait,
+) -> syn::Result<TokenStream> {
+    if !item.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            item.generics.__span(),
+            "syscall handler traits cannot be generic.",
+        ));
+    }
+
+    let mut handlers = Vec::new();
+    for trait_item in &mut item.items {
+        if let TraitItem::Fn(method) = trait_item {
+            handlers.push(extract_handler(method, context.is_some())?);
+        }
+    }
+
+    let trait_ident = &item.ident;
+    let vis = &item.vis;
+    let table_ident = format_ident!("{}Table", trait_ident);
+    let table_doc = format!(
+        "A syscall table that dispatches each syscall to its method of an implementation of [{}].",
+        trait_ident
+    );
+
+    let (ctx_ty, ctx_param, ctx_arg) = match &context {
+        Some(ctx) => (quote! {#ctx}, quote! {ctx}, Some(quote! {ctx,})),
+        None => (quote! {()}, quote! {_}, None),
+    };
+
+    let (fast, normal): (Vec<_>, Vec<_>) = handlers.iter().partition(|h| h.fast);
+    let normal = normal.iter().map(|h| {
+        let ty = &h.ty;
+        let method = &h.method;
+        let upgrades = &h.upgrades;
+        let upgrades = (!upgrades.is_empty()).then(|| quote! {, upgrades = [#(#upgrades),*]});
+        let restartable = h.restartable.then(|| quote! {, restartable = true});
+        quote! {(#ty, |#ctx_param, _num, args| self.handlers.#method(#ctx_arg args) #upgrades #restartable)}
+    });
+    let fast_uses =
+        (!fast.is_empty()).then(|| quote! {use ::syscall_encode_traits::api::SyscallFastApi;});
+    let fast = fast.iter().map(|h| {
+        let ty = &h.ty;
+        let method = &h.method;
+        quote! {(#ty, |#ctx_param, _num, args| self.handlers.#method(#ctx_arg args))}
+    });
+
+    Ok(quote! {
+        #item
+
+        #[doc = #table_doc]
+        #vis struct #table_ident<'a, H: ?Sized> {
+            abi: &'a #abi,
+            handlers: &'a H,
+        }
+
+        impl<'a, H: ?Sized> #table_ident<'a, H> {
+            /// Create a new table, dispatching calls made with abi to handlers.
+            #vis fn new(abi: &'a #abi, handlers: &'a H) -> Self {
+                Self { abi, handlers }
+            }
+        }
+
+        impl<'a, H: #trait_ident + ?Sized> ::syscall_encode_traits::table::SyscallTable<#abi, #ctx_ty> for #table_ident<'a, H> {
+            fn handle_call(
+                &self,
+                ctx: &mut #ctx_ty,
+                num: <#abi as ::syscall_encode_traits::abi::SyscallAbi>::SyscallNumType,
+                arg: <#abi as ::syscall_encode_traits::abi::SyscallAbi>::SyscallArgType,
+            ) -> <#abi as ::syscall_encode_traits::abi::SyscallAbi>::SyscallRetType {
+                use ::syscall_encode_traits::{api::SyscallApi, error::SyscallError};
+                #fast_uses
+                // Safety: a syscall table is only handed numbers and arguments that were received together.
+                unsafe {
+                    ::syscall_encode_traits::syscall_api! {
+                        number = num;
+                        args = arg;
+                        abi_type = #abi;
+                        abi = self.abi;
+                        context = ctx;
+                        handlers = { #(#normal),* }
+                        fast_handlers = { #(#fast),* }
+                    }
+                }
+            }
+        }
+    })
+}
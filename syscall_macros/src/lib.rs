@@ -1,8 +1,15 @@
 use proc_macro::TokenStream;
 use syn::{parse_macro_input, DeriveInput};
 
+mod abi_hash;
+mod commands;
+mod error_code;
+mod extensible;
+mod handlers;
+mod non_exhaustive;
 mod syscall;
-#[proc_macro_derive(SyscallEncodable, attributes(reg_bits, num_regs))]
+mod user_copy;
+#[proc_macro_derive(SyscallEncodable, attributes(reg_bits, num_regs, syscall))]
 pub fn syscall_encodable_proc_macro(input: TokenStream) -> TokenStream {
     let derive_input: DeriveInput = parse_macro_input!(input as DeriveInput);
     match syscall::derive_proc_macro_impl(derive_input) {
@@ -10,3 +17,132 @@ pub fn syscall_encodable_proc_macro(input: TokenStream) -> TokenStream {
         Err(err) => TokenStream::from(err.to_compile_error()),
     }
 }
+
+#[proc_macro_derive(UserCopy)]
+pub fn user_copy_proc_macro(input: TokenStream) -> TokenStream {
+    let derive_input: DeriveInput = parse_macro_input!(input as DeriveInput);
+    match user_copy::derive_proc_macro_impl(derive_input) {
+        Ok(ts) => ts.into(),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
+
+/// Turn an enum of commands into a multiplexed syscall, ioctl style, where each command has its own return and
+/// error types. The enum must also derive SyscallEncodable. For an enum Cmd, this generates a typed method per
+/// command for performing it, the CmdReturn and CmdError enums that carry each command's result, a CmdHandler
+/// trait with a method per command for the kernel to implement, and Cmd::dispatch, which calls the handler for
+/// a command. For example:
+///
+/// ```no_compile
+/// #[derive(SyscallEncodable, SyscallCommands, Clone, Copy, Debug)]
+/// #[repr(C)]
+/// #[command(abi = X86Abi, num = 7)]
+/// enum DevCmd {
+///     #[command(returns = u32, error = DevErr)]
+///     Read(ReadArgs),
+///     // Methods are named after their variant, unless given a name.
+///     #[command(error = DevErr, name = reset_all)]
+///     Reset,
+/// }
+///
+/// let value = DevCmd::read(&abi, args)?;
+/// ```
+#[proc_macro_derive(SyscallCommands, attributes(command))]
+pub fn syscall_commands_proc_macro(input: TokenStream) -> TokenStream {
+    let derive_input: DeriveInput = parse_macro_input!(input as DeriveInput);
+    match commands::derive_proc_macro_impl(derive_input) {
+        Ok(ts) => ts.into(),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
+
+/// Make an enum that derives SyscallEncodable non-exhaustive, by adding an `Unknown(u64)` variant and marking it
+/// `#[syscall(non_exhaustive)]`, so that discriminants it doesn't know about decode into the Unknown variant. Must
+/// come before the derive. For example:
+///
+/// ```no_compile
+/// #[syscall_non_exhaustive]
+/// #[derive(SyscallEncodable, Clone, Copy, Debug)]
+/// #[repr(C)]
+/// enum OpenErr {
+///     NotFound,
+///     // Unknown(u64) is added here.
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn syscall_non_exhaustive(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return TokenStream::from(
+            syn::Error::new(
+                proc_macro2::Span::call_site(),
+                "syscall_non_exhaustive takes no arguments.",
+            )
+            .to_compile_error(),
+        );
+    }
+    let item = parse_macro_input!(item as syn::ItemEnum);
+    match non_exhaustive::syscall_non_exhaustive_impl(item) {
+        Ok(ts) => ts.into(),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
+
+#[proc_macro_derive(ErrorCode, attributes(code, message, errno, syscall))]
+pub fn error_code_proc_macro(input: TokenStream) -> TokenStream {
+    let derive_input: DeriveInput = parse_macro_input!(input as DeriveInput);
+    match error_code::derive_proc_macro_impl(derive_input) {
+        Ok(ts) => ts.into(),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
+
+/// Derive a syscall table from a trait with one method per syscall, each taking &self and the syscall's
+/// arguments, and returning the syscall's result. For a trait Kernel, this generates a KernelTable type that
+/// implements SyscallTable for the given ABI by calling the methods of a Kernel implementation, so every
+/// syscall in the table must have a handler. Methods of fast syscalls are marked `#[syscall(fast)]`, and older
+/// versions of a syscall that the handler accepts are listed with `#[syscall(upgrades = [FooV1])]`. Methods
+/// marked `#[syscall(restartable)]` return a SyscallError of the syscall's error type, as in syscall_api. With
+/// `context = Ctx`, the table implements `SyscallTable<Abi, Ctx>`, and each method takes a `&mut Ctx` before
+/// the syscall's arguments. For example:
+///
+/// ```no_compile
+/// #[syscall_handlers(abi = X86Abi)]
+/// trait Kernel {
+///     fn foo(&self, args: Foo) -> Result<FooRet, FooErr>;
+///     #[syscall(fast)]
+///     fn fast_foo(&self, args: FastFoo) -> FastFooRet;
+/// }
+///
+/// let table = KernelTable::new(&abi, &kernel);
+/// ```
+#[proc_macro_attribute]
+pub fn syscall_handlers(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut abi = None;
+    let mut context = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("abi") {
+            abi = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("context") {
+            context = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("unknown syscall_handlers option."))
+        }
+    });
+    parse_macro_input!(attr with parser);
+    let item = parse_macro_input!(item as syn::ItemTrait);
+    let Some(abi) = abi else {
+        return TokenStream::from(
+            syn::Error::new(
+                proc_macro2::Span::call_site(),
+                "syscall_handlers requires an abi = Type argument.",
+            )
+            .to_compile_error(),
+        );
+    };
+    match handlers::syscall_handlers_impl(abi, context, item) {
+        Ok(ts) => ts.into(),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
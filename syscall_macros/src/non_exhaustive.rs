@@ -0,0 +1,18 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_quote, spanned::Spanned, ItemEnum};
+
+pub fn syscall_non_exhaustive_impl(mut item: ItemEnum) -> Result<TokenStream, syn::Error> {
+    if let Some(var) = item.variants.iter().find(|var| var.ident == "Unknown") {
+        return Err(syn::Error::new(
+            var.span(),
+            "syscall_non_exhaustive generates the Unknown variant. Use #[syscall(non_exhaustive)] to declare it by hand.",
+        ));
+    }
+    item.variants.push(parse_quote! {
+        /// A discriminant that this version does not know about, e.g. of a variant added by a newer kernel.
+        Unknown(u64)
+    });
+    item.attrs.push(parse_quote!(#[syscall(non_exhaustive)]));
+    Ok(quote!(#item))
+}
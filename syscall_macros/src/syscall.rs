@@ -111,15 +111,29 @@ pub fn derive_proc_macro_impl(input: DeriveInput) -> Result<TokenStream, syn::Er
     } = input;
 
     let mut has_repr_c = false;
+    let mut non_exhaustive = false;
+    let mut extensible = false;
     for attr in attrs {
         if attr.path().is_ident("repr") {
             let repr: Ident = attr.meta.require_list()?.parse_args()?;
             if repr == *"C" {
                 has_repr_c = true;
-                break;
+                continue;
             }
             return Err(syn::Error::new(repr.span(), "SyscallEncodable requires #[repr(C)]."));
         }
+        if attr.path().is_ident("syscall") {
+            let opt: Ident = attr.meta.require_list()?.parse_args()?;
+            if opt == *"non_exhaustive" {
+                non_exhaustive = true;
+                continue;
+            }
+            if opt == *"extensible" {
+                extensible = true;
+                continue;
+            }
+            return Err(syn::Error::new(opt.span(), format!("unknown syscall option {}.", opt)));
+        }
     }
 
     if !has_repr_c {
@@ -131,14 +145,22 @@ pub fn derive_proc_macro_impl(input: DeriveInput) -> Result<TokenStream, syn::Er
 
     //let required_trait_bounds = vec!["core::default::Default", "core::fmt::Debug"];
     let streams = match &data {
+        syn::Data::Struct(_) if non_exhaustive => Err(syn::Error::new(span, "#[syscall(non_exhaustive)] is only supported on enums.")),
         syn::Data::Struct(st) => handle_struct(span, st),
-        syn::Data::Enum(en) => handle_enum(span, struct_name_ident.clone(), en),
+        syn::Data::Enum(en) => handle_enum(span, struct_name_ident.clone(), en, non_exhaustive),
         syn::Data::Union(_) => todo!(),
     }?;
 
     let encode_stream = streams.0;
     let decode_stream = streams.1;
 
+    let extensible_stream = match &data {
+        syn::Data::Struct(st) if extensible => crate::extensible::derive_extensible(&struct_name_ident, &generics, st)?,
+        _ if extensible => return Err(syn::Error::new(span, "#[syscall(extensible)] is only supported on structs.")),
+        _ => TokenStream::new(),
+    };
+    let abi_hash_stream = crate::abi_hash::derive_abi_hash(&struct_name_ident, &generics, &data, non_exhaustive)?;
+
     use syn::spanned::Spanned;
     let struct_generics = generics.clone();
 
@@ -207,23 +229,41 @@ pub fn derive_proc_macro_impl(input: DeriveInput) -> Result<TokenStream, syn::Er
                 #decode_stream
             }
         }
+
+        #extensible_stream
+
+        #abi_hash_stream
     })
 }
 
 fn handle_enum(
-    _span: Span,
+    span: Span,
     ident: Ident,
     en: &DataEnum,
+    non_exhaustive: bool,
 ) -> syn::Result<(TokenStream, TokenStream)> {
     for var in &en.variants {
         for f in &var.fields {
             check_ty_allowed(f.ty.__span(), &f.ty)?;
         }
     } 
+
+    // With non_exhaustive, the Unknown variant holds any discriminant we don't recognize, and
+    // doesn't get a discriminant of its own.
+    if non_exhaustive {
+        match en.variants.iter().find(|var| var.ident == "Unknown").map(|var| &var.fields) {
+            Some(syn::Fields::Unnamed(fields)) if fields.unnamed.len() == 1 => {}
+            _ => return Err(syn::Error::new(span, "#[syscall(non_exhaustive)] requires an Unknown(u64) variant to hold unknown discriminants.")),
+        }
+    }
+    let variants: Vec<_> = en
+        .variants
+        .iter()
+        .filter(|var| !(non_exhaustive && var.ident == "Unknown"))
+        .collect();
                
     let encode = {
-        let internal: Vec<_> = en
-            .variants
+        let mut internal: Vec<_> = variants
             .iter()
             .enumerate()
             .map(|(num, var)| {
@@ -268,6 +308,11 @@ fn handle_enum(
                 }
             })
             .collect();
+        if non_exhaustive {
+            internal.push(quote! {
+                Self::Unknown(disc) => {disc.encode(encoder)?;}
+            });
+        }
         if internal.is_empty() {
             quote! {Ok(())}
         } else {        quote! {match self {#(#internal)*}; Ok(())}
@@ -275,13 +320,13 @@ fn handle_enum(
     };
 
     let decode = {
-        let internal: Vec<_> = en
-            .variants
+        let internal: Vec<_> = variants
             .iter()
             .enumerate()
             .map(|(num, var)| {
                 let num = num as u64;
                 let name = &var.ident;
+                let variant = name.to_string();
 
                 let (_names, structure, code) = match &var.fields {
                     syn::Fields::Named(fields) => {
@@ -292,9 +337,13 @@ fn handle_enum(
                             .collect();
                         let code: Vec<_> = fields.named.iter().map(|field| {
                             let name = field.ident.as_ref().unwrap();
+                            let field_name = name.to_string();
                             let ty = &field.ty;
                             quote!{
-                                let #name = <#ty as ::syscall_encode_traits::api::SyscallEncodable<'abi, Abi, EncodedType, Encoder>>::decode(decoder)?;                            
+                                let #name = <#ty as ::syscall_encode_traits::api::SyscallEncodable<'abi, Abi, EncodedType, Encoder>>::decode(decoder).map_err(|e| {
+                                    let e = decoder.record_decode_error(Some(#field_name), e);
+                                    decoder.record_decode_error(Some(#variant), e)
+                                })?;
                             }
                         }).collect();
                         (names.clone(), quote!({#(#names),*}), code)
@@ -310,11 +359,15 @@ fn handle_enum(
                             })
                             .collect();
                         
-                        let code: Vec<_> = fields.unnamed.iter().zip(names.iter()).map(|(field, ident)| {
+                        let code: Vec<_> = fields.unnamed.iter().zip(names.iter()).enumerate().map(|(num, (field, ident))| {
                             let name = ident;
+                            let field_name = num.to_string();
                             let ty = &field.ty;
                             quote!{
-                                let #name = <#ty as ::syscall_encode_traits::api::SyscallEncodable<'abi, Abi, EncodedType, Encoder>>::decode(decoder)?;                            
+                                let #name = <#ty as ::syscall_encode_traits::api::SyscallEncodable<'abi, Abi, EncodedType, Encoder>>::decode(decoder).map_err(|e| {
+                                    let e = decoder.record_decode_error(Some(#field_name), e);
+                                    decoder.record_decode_error(Some(#variant), e)
+                                })?;
                             }
                         }).collect();                       
                         (names.clone(), quote!((#(#names),*)), code)
@@ -329,14 +382,19 @@ fn handle_enum(
                 }
             })
             .collect();
-        if internal.is_empty() {
+        let fallback = if non_exhaustive {
+            quote! {_ => Self::Unknown(disc)}
+        } else {
+            quote! {_ => return Err(decoder.record_decode_error(None, ::syscall_encode_traits::encoder::DecodeError::BadDiscriminant))}
+        };
+        if internal.is_empty() && !non_exhaustive {
             quote!{Ok(#ident)}
         } else {
                     quote! {
             let disc = u64::decode(decoder)?;
             Ok(match disc {
                 #(#internal)*
-                _ => return Err(::syscall_encode_traits::encoder::DecodeError::InvalidData)
+                #fallback
             })
         }
     }
@@ -376,8 +434,9 @@ fn handle_struct(_span: Span, st: &DataStruct) -> syn::Result<(TokenStream, Toke
                 .iter()
                 .map(|field| {
                     let name = field.ident.as_ref().unwrap();
+                    let field_name = name.to_string();
                     let ty = &field.ty;
-                    quote! {#name : <#ty as ::syscall_encode_traits::api::SyscallEncodable<'abi, Abi, EncodedType, Encoder>>::decode(decoder)?}
+                    quote! {#name : <#ty as ::syscall_encode_traits::api::SyscallEncodable<'abi, Abi, EncodedType, Encoder>>::decode(decoder).map_err(|e| decoder.record_decode_error(Some(#field_name), e))?}
                 })
                 .collect();
             quote! {Ok(Self{#(#internal),*})}
@@ -387,9 +446,10 @@ fn handle_struct(_span: Span, st: &DataStruct) -> syn::Result<(TokenStream, Toke
                 .unnamed
                 .iter()
                 .enumerate()
-                .map(|(_num, field)| {
+                .map(|(num, field)| {
+                    let field_name = num.to_string();
                     let ty = &field.ty;
-                    quote! {<#ty as ::syscall_encode_traits::api::SyscallEncodable<'abi, Abi, EncodedType, Encoder>>::decode(decoder)?}
+                    quote! {<#ty as ::syscall_encode_traits::api::SyscallEncodable<'abi, Abi, EncodedType, Encoder>>::decode(decoder).map_err(|e| decoder.record_decode_error(Some(#field_name), e))?}
                 })
                 .collect();
             quote! {Ok(Self(#(#internal),*))}
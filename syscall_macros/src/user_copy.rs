@@ -0,0 +1,63 @@
+use proc_macro2::TokenStream;
+use quote::{quote, spanned::Spanned};
+use syn::{DataStruct, DeriveInput};
+
+pub fn derive_proc_macro_impl(input: DeriveInput) -> Result<TokenStream, syn::Error> {
+    let span = input.__span();
+    let DeriveInput {
+        ident: struct_name_ident,
+        data,
+        generics,
+        ..
+    } = input;
+
+    let body = match &data {
+        syn::Data::Struct(st) => handle_struct(st),
+        // Not every bit pattern is a valid discriminant, so enums can't be copied from user memory as raw bytes.
+        syn::Data::Enum(_) => Err(syn::Error::new(
+            span,
+            "UserCopy cannot be derived for enums, as user memory may hold invalid discriminants. Use an integer instead.",
+        )),
+        syn::Data::Union(_) => Err(syn::Error::new(
+            span,
+            "UserCopy cannot be derived for unions.",
+        )),
+    }?;
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        // Safety: every field is UserCopy, so every bit pattern of the struct is valid.
+        unsafe impl #impl_generics ::syscall_encode_traits::deep_copy::UserCopy for #struct_name_ident #ty_generics #where_clause {
+            fn copy_refs<F: FnMut(*const u8, usize) -> bool>(
+                &mut self,
+                ctx: &mut ::syscall_encode_traits::deep_copy::DeepCopy<'_, F>,
+            ) -> Result<(), ::syscall_encode_traits::deep_copy::DeepCopyError> {
+                #body
+            }
+        }
+    })
+}
+
+fn handle_struct(st: &DataStruct) -> syn::Result<TokenStream> {
+    let code: Vec<_> = st
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(num, field)| {
+            let num = syn::Index::from(num);
+            match field.ident.as_ref() {
+                Some(name) => quote! {
+                    ::syscall_encode_traits::deep_copy::UserCopy::copy_refs(&mut self.#name, ctx)?;
+                },
+                None => quote! {
+                    ::syscall_encode_traits::deep_copy::UserCopy::copy_refs(&mut self.#num, ctx)?;
+                },
+            }
+        })
+        .collect();
+    Ok(quote! {
+        #(#code)*
+        Ok(())
+    })
+}
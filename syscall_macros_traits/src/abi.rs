@@ -1,7 +1,13 @@
 use core::alloc::Layout;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
 use core::ptr::null_mut;
 
-use crate::{api::SyscallEncodable, encoder::SyscallEncoder, error::SyscallError};
+use crate::{
+    api::SyscallEncodable,
+    encoder::{context::DecodeContext, AllocEncoder, SyscallEncoder},
+    error::SyscallError,
+};
 
 pub mod registers_and_stack;
 
@@ -11,48 +17,87 @@ pub trait SyscallAbi: Sized {
     type SyscallArgType: Copy;
     /// The type that will be returned by the syscall implementation.
     type SyscallRetType: Copy;
-    /// The type that will be passed to the syscall implementation function as the number.
+    /// The type that will be passed to the syscall implementation function as the number. Tables match on
+    /// syscall numbers, so constants of this type must be usable as patterns: it should be a primitive integer, or
+    /// derive PartialEq and Eq.
     type SyscallNumType: Copy;
 
-    // The type of the argument encoder for this ABI.
-    type ArgEncoder<'a>: SyscallEncoder<'a, Self, Self::SyscallArgType>
+    // The type of the argument encoder for this ABI, encoding values that live for 'a into an allocation that
+    // lives for 'buf. Decoders don't allocate, and use 'static.
+    type ArgEncoder<'a, 'buf>: SyscallEncoder<'a, Self, Self::SyscallArgType>
+        + AllocEncoder<'a, 'buf, Self>
     where
         Self: 'a;
     // The type of the return value encoder for this ABI.
-    type RetEncoder<'a>: SyscallEncoder<'a, Self, Self::SyscallRetType>
+    type RetEncoder<'a, 'buf>: SyscallEncoder<'a, Self, Self::SyscallRetType>
+        + AllocEncoder<'a, 'buf, Self>
     where
         Self: 'a;
 
+    /// If true, every call sends the fingerprint of its syscall (see [crate::api::SyscallApi::FINGERPRINT]) ahead
+    /// of its arguments, and the kernel table rejects calls whose fingerprint doesn't match its own with
+    /// [SyscallError::AbiMismatch]. Userspace and the kernel must agree on this setting.
+    const CHECK_FINGERPRINTS: bool = false;
+
     /// Allocate some memory with the supplied layout. The allocation mechanism should issue no syscalls.
-    /// The allocated memory is passed to the supplied closure.
+    /// The allocated memory is passed to the supplied closure, and cannot outlive it. Implementations can
+    /// delegate to one of the strategies in [crate::spill], e.g. `StackSpill::<256>.with_spill(layout, f)`.
     fn with_alloc<F, R, E: Copy>(&self, layout: Layout, f: F) -> Result<R, SyscallError<E>>
     where
-        F: FnOnce(Allocation) -> Result<R, SyscallError<E>>;
+        F: FnOnce(Allocation<'_>) -> Result<R, SyscallError<E>>;
 
     /// Allocate some memory as the kernel, for passing back to the application. This can just
     /// return a null Allocation.
     ///
     /// # Safety
     /// The allocation must point to memory that will be valid until the application fully reads and decodes the value.
-    unsafe fn kernel_alloc(&self, layout: Layout) -> Allocation;
+    unsafe fn kernel_alloc(&self, layout: Layout) -> Allocation<'_>;
+
+    /// Translate a local virtual address into an object ID and a byte offset within that object, for encoding
+    /// an [crate::ptr::ObjectPointer]. Returns None if the address cannot be translated, which is the default.
+    fn addr_to_object(&self, _addr: usize) -> Option<(u128, u64)> {
+        None
+    }
+
+    /// Translate an object ID and a byte offset into a local virtual address, for using a decoded
+    /// [crate::ptr::ObjectPointer]. Returns None if the object is not mapped, which is the default.
+    fn object_to_addr(&self, _id: u128, _offset: u64) -> Option<usize> {
+        None
+    }
+
+    /// Called when decoding the arguments (in the kernel) or the return value (in userspace) of a syscall
+    /// fails, with the context recorded by the decoder, e.g. for logging. Does nothing by default.
+    fn decode_failed(&self, _context: DecodeContext<Self::SyscallNumType>) {}
+
+    /// Combine a syscall number with the version of the syscall being called, producing the number passed to
+    /// [Self::syscall_impl]. The version may be packed into the number, or sent some other way. By default,
+    /// versions are not sent, and every call is treated as version 0.
+    fn versioned_num(&self, num: Self::SyscallNumType, _version: u32) -> Self::SyscallNumType {
+        num
+    }
+
+    /// Split a number produced by [Self::versioned_num] back into the syscall number and version.
+    fn split_versioned_num(&self, num: Self::SyscallNumType) -> (Self::SyscallNumType, u32) {
+        (num, 0)
+    }
 
-    /// Create a new encoder for arguments.
-    fn arg_encoder(&self, alloc: Allocation) -> Self::ArgEncoder<'_> {
+    /// Create a new encoder for arguments. The encoder borrows alloc, so it cannot outlive the allocation.
+    fn arg_encoder<'a, 'buf>(&'a self, alloc: Allocation<'buf>) -> Self::ArgEncoder<'a, 'buf> {
         Self::ArgEncoder::new_encode(self, alloc)
     }
 
     /// Create a new decoder for arguments.
-    fn arg_decoder(&self, data: Self::SyscallArgType) -> Self::ArgEncoder<'_> {
+    fn arg_decoder(&self, data: Self::SyscallArgType) -> Self::ArgEncoder<'_, 'static> {
         Self::ArgEncoder::new_decode(self, data)
     }
 
-    /// Create a new encoder for return values.
-    fn ret_encoder(&self, alloc: Allocation) -> Self::RetEncoder<'_> {
+    /// Create a new encoder for return values. The encoder borrows alloc, so it cannot outlive the allocation.
+    fn ret_encoder<'a, 'buf>(&'a self, alloc: Allocation<'buf>) -> Self::RetEncoder<'a, 'buf> {
         Self::RetEncoder::new_encode(self, alloc)
     }
 
     /// Create a new decoder for return values.
-    fn ret_decoder(&self, data: Self::SyscallRetType) -> Self::RetEncoder<'_> {
+    fn ret_decoder(&self, data: Self::SyscallRetType) -> Self::RetEncoder<'_, 'static> {
         Self::RetEncoder::new_decode(self, data)
     }
 
@@ -79,43 +124,111 @@ pub trait SyscallAbi: Sized {
     );
 }
 
-/// An allocation that was performed for storing encoded values.
-pub struct Allocation {
+/// An allocation that was performed for storing encoded values, borrowing the memory it came from for 'buf. Memory
+/// is reserved from the start of the allocation onwards, and reservations may be undone back to a [Mark].
+pub struct Allocation<'buf> {
     data: *mut u8,
     size: usize,
     taken: usize,
+    _pd: PhantomData<&'buf mut [u8]>,
 }
 
-impl Allocation {
+/// A point in an [Allocation] that later reservations can be undone back to, with [Allocation::rollback].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Mark(usize);
+
+impl<'buf> Allocation<'buf> {
     /// Create a new null allocation.
     pub fn null() -> Self {
         Self {
             data: null_mut(),
             size: 0,
             taken: 0,
+            _pd: PhantomData,
         }
     }
 
-    /// Allocate some memory.
-    pub fn reserve<T: Copy>(&mut self) -> Option<&mut T> {
+    /// Create an allocation of size bytes starting at data, e.g. for memory that the kernel maps into the
+    /// application.
+    ///
+    /// # Safety
+    /// The memory must be valid for reads and writes of size bytes, initialized, and not otherwise accessed, for 'buf.
+    pub unsafe fn from_raw_parts(data: *mut u8, size: usize) -> Self {
+        Self {
+            data,
+            size,
+            taken: 0,
+            _pd: PhantomData,
+        }
+    }
+
+    // Reserve size bytes aligned to align, which must be a power of two.
+    fn reserve_raw(&mut self, size: usize, align: usize) -> Option<*mut u8> {
         if self.is_null() {
             return None;
         }
-        let layout = core::alloc::Layout::new::<T>();
         // Safety: we meet the stated requirements of add(), since taken will never exceed size.
-        let a_off = unsafe { self.data.add(self.taken) }.align_offset(layout.align());
+        let a_off = unsafe { self.data.add(self.taken) }.align_offset(align);
         if a_off == usize::MAX {
             return None;
         }
-        self.taken += a_off;
-        if self.taken + layout.size() > self.size {
+        let start = self.taken.checked_add(a_off)?;
+        let end = start.checked_add(size)?;
+        if end > self.size {
+            return None;
+        }
+        self.taken = end;
+        // Safety: start does not exceed size, so the pointer stays within the allocation.
+        Some(unsafe { self.data.add(start) })
+    }
+
+    /// Allocate some memory.
+    pub fn reserve<T: Copy>(&mut self) -> Option<&mut T> {
+        let layout = Layout::new::<T>();
+        let ptr = self.reserve_raw(layout.size(), layout.align())?;
+        // Safety: the memory is within the allocation, aligned for T, and reserved only once until a rollback,
+        // which needs &mut self.
+        unsafe { (ptr as *mut T).as_mut() }
+    }
+
+    /// Allocate len bytes, aligned to align. Returns None if align is not a power of two, or there isn't enough
+    /// room.
+    pub fn reserve_bytes(&mut self, len: usize, align: usize) -> Option<&mut [u8]> {
+        if !align.is_power_of_two() {
             return None;
         }
-        // Safety: again, taken will never exceed size, nor will taken + layout.size() ever exceed size.
-        // The cast is safe as well, since we manually aligned above.
-        let res = unsafe { (self.data.add(self.taken) as *mut T).as_mut() };
-        self.taken += layout.size();
-        res
+        let ptr = self.reserve_raw(len, align)?;
+        // Safety: as in reserve, and the allocation's memory is initialized.
+        Some(unsafe { core::slice::from_raw_parts_mut(ptr, len) })
+    }
+
+    /// Allocate room for n values of type T, which are left uninitialized.
+    pub fn reserve_slice<T: Copy>(&mut self, n: usize) -> Option<&mut [MaybeUninit<T>]> {
+        let layout = Layout::array::<T>(n).ok()?;
+        let ptr = self.reserve_raw(layout.size(), layout.align())?;
+        // Safety: as in reserve.
+        Some(unsafe { core::slice::from_raw_parts_mut(ptr as *mut MaybeUninit<T>, n) })
+    }
+
+    /// The number of bytes that are not yet reserved. Reservations may need some of these for alignment.
+    pub fn remaining(&self) -> usize {
+        self.size - self.taken
+    }
+
+    /// The number of bytes reserved so far, including padding for alignment.
+    pub fn used(&self) -> usize {
+        self.taken
+    }
+
+    /// Mark the current point in the allocation.
+    pub fn mark(&self) -> Mark {
+        Mark(self.taken)
+    }
+
+    /// Undo every reservation made since mark was taken, so that the memory can be reserved again. Marks past the
+    /// current point, e.g. ones taken before an earlier rollback, are ignored.
+    pub fn rollback(&mut self, mark: Mark) {
+        self.taken = self.taken.min(mark.0);
     }
 
     /// Is this a null allocation?
@@ -124,12 +237,13 @@ impl Allocation {
     }
 }
 
-impl From<&mut [u8]> for Allocation {
-    fn from(value: &mut [u8]) -> Self {
+impl<'buf> From<&'buf mut [u8]> for Allocation<'buf> {
+    fn from(value: &'buf mut [u8]) -> Self {
         Self {
             data: value.as_mut_ptr(),
             size: value.len(),
             taken: 0,
+            _pd: PhantomData,
         }
     }
 }
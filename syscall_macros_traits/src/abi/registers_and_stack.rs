@@ -4,25 +4,27 @@ use core::{fmt::Debug, ops::BitXor};
 
 use crate::{
     api::{impls::EncodeAllPrimitives, SyscallEncodable},
-    encoder::{DecodeError, EncodeError, SyscallEncoder},
+    encoder::{context::DecodeContext, AllocEncoder, DecodeError, EncodeError, SyscallEncoder},
 };
 
 use super::{Allocation, SyscallAbi};
 
 /// A basic encoder that encodes values byte-by-byte into registers, or the stack if we spill over. Somewhat slow,
 /// but can encode all the basic types. Can be configured by the register type (u32, u64, etc) and the number of registers
-/// that can be used as syscall arg registers.
+/// that can be used as syscall arg registers. Spilled bytes go into an allocation borrowed for 'buf.
 pub struct RegistersAndStackEncoder<
     'a,
+    'buf,
     Abi: SyscallAbi,
     RegisterType: Copy + Default,
     const NR_REGS: usize,
 > {
-    _abi: &'a Abi,
+    abi: &'a Abi,
     idx: usize,
     by: usize,
     regs: RegisterAndStackData<RegisterType, NR_REGS>,
-    alloc: Allocation,
+    alloc: Allocation<'buf>,
+    context: Option<DecodeContext>,
 }
 
 /// An allowed register type for the RegistersAndStackEncoder.
@@ -32,36 +34,36 @@ impl AllowedRegisterType for u64 {}
 impl AllowedRegisterType for u32 {}
 impl AllowedRegisterType for u128 {}
 
-impl<'a, Abi: SyscallAbi, RegisterType: Copy + Default, const NR_REGS: usize>
-    RegistersAndStackEncoder<'a, Abi, RegisterType, NR_REGS>
+impl<'a, 'buf, Abi: SyscallAbi, RegisterType: Copy + Default, const NR_REGS: usize>
+    RegistersAndStackEncoder<'a, 'buf, Abi, RegisterType, NR_REGS>
 {
     const REG_BYTES: usize = core::mem::size_of::<RegisterType>();
+
+    // Spilled bytes are counted after the bytes of all the registers before the spill pointer.
+    fn offset(&self) -> usize {
+        self.idx * Self::REG_BYTES + self.by
+    }
 }
 
-impl<'a, Abi: SyscallAbi, RegisterType: Copy + Default, const NR_REGS: usize>
+impl<'a, 'buf, Abi: SyscallAbi, RegisterType: Copy + Default, const NR_REGS: usize>
     SyscallEncoder<'a, Abi, RegisterAndStackData<RegisterType, NR_REGS>>
-    for RegistersAndStackEncoder<'a, Abi, RegisterType, NR_REGS>
+    for RegistersAndStackEncoder<'a, 'buf, Abi, RegisterType, NR_REGS>
 where
     RegisterType: AllowedRegisterType,
 {
     fn new_decode(abi: &'a Abi, decode_data: RegisterAndStackData<RegisterType, NR_REGS>) -> Self {
         Self {
-            _abi: abi,
+            abi,
             regs: decode_data,
             idx: 0,
             alloc: Allocation::null(),
             by: 0,
+            context: None,
         }
     }
 
-    fn new_encode(abi: &'a Abi, allocation: Allocation) -> Self {
-        Self {
-            _abi: abi,
-            regs: Default::default(),
-            idx: 0,
-            alloc: allocation,
-            by: 0,
-        }
+    fn abi(&self) -> Option<&Abi> {
+        Some(self.abi)
     }
 
     fn encode<
@@ -77,6 +79,25 @@ where
         self.regs
     }
 
+    fn record_decode_error(
+        &mut self,
+        field: Option<&'static str>,
+        err: DecodeError,
+    ) -> DecodeError {
+        let offset = self.offset();
+        let context = self
+            .context
+            .get_or_insert_with(|| DecodeContext::new(err, offset));
+        if let Some(field) = field {
+            context.push_field(field);
+        }
+        err
+    }
+
+    fn decode_context(&self) -> Option<DecodeContext> {
+        self.context
+    }
+
     fn decode<
         Target: SyscallEncodable<'a, Abi, RegisterAndStackData<RegisterType, NR_REGS>, Self>,
     >(
@@ -147,6 +168,10 @@ where
             Ok(item)
         } else {
             let reg: u128 = self.regs.regs[self.idx].into();
+            // The encoder always stores a valid pointer here before spilling.
+            if reg == 0 {
+                return Err(DecodeError::SpillFault);
+            }
             #[cfg(miri)]
             let base_ptr = self.regs.ptr.with_addr(reg as usize);
             #[cfg(not(miri))]
@@ -186,9 +211,24 @@ impl<R: Copy + Default, const NR_REGS: usize> Default for RegisterAndStackData<R
     }
 }
 
-impl<'a, Abi: SyscallAbi, RegisterType: Copy + Default, const NR_REGS: usize>
+impl<'a, 'buf, Abi: SyscallAbi, RegisterType: Copy + Default, const NR_REGS: usize>
+    AllocEncoder<'a, 'buf, Abi> for RegistersAndStackEncoder<'a, 'buf, Abi, RegisterType, NR_REGS>
+{
+    fn new_encode(abi: &'a Abi, allocation: Allocation<'buf>) -> Self {
+        Self {
+            abi,
+            regs: Default::default(),
+            idx: 0,
+            alloc: allocation,
+            by: 0,
+            context: None,
+        }
+    }
+}
+
+impl<'a, 'buf, Abi: SyscallAbi, RegisterType: Copy + Default, const NR_REGS: usize>
     EncodeAllPrimitives<'a, Abi, RegisterAndStackData<RegisterType, NR_REGS>, Self>
-    for RegistersAndStackEncoder<'a, Abi, RegisterType, NR_REGS>
+    for RegistersAndStackEncoder<'a, 'buf, Abi, RegisterType, NR_REGS>
 where
     RegisterType: AllowedRegisterType,
 {
@@ -4,6 +4,8 @@ use crate::{
     abi::SyscallAbi,
     encoder::{DecodeError, EncodeError, SyscallEncoder},
     error::SyscallError,
+    fingerprint::{hash_u64, AbiHash},
+    spill::{AbiSpill, SpillAllocator},
 };
 
 pub mod impls;
@@ -13,37 +15,95 @@ pub mod impls;
 /// call the auto implemented perform_call function on a value of the type
 /// implementing this trait.
 pub trait SyscallApi<'a, Abi: SyscallAbi + 'a>:
-    SyscallEncodable<'a, Abi, Abi::SyscallArgType, Abi::ArgEncoder<'a>>
+    for<'buf> SyscallEncodable<'a, Abi, Abi::SyscallArgType, Abi::ArgEncoder<'a, 'buf>> + AbiHash
 {
     /// The number of the syscall. Must be unique.
     const NUM: Abi::SyscallNumType;
+    /// The version of the syscall's arguments, sent along with NUM (see [SyscallAbi::versioned_num]). Older versions
+    /// of a syscall keep the same NUM, and may be accepted by the kernel table and upgraded to the current version.
+    const VERSION: u32 = 0;
     /// The type returned by this call on success.
-    type ReturnType: SyscallEncodable<'a, Abi, Abi::SyscallRetType, Abi::RetEncoder<'a>>;
+    type ReturnType: for<'buf> SyscallEncodable<'a, Abi, Abi::SyscallRetType, Abi::RetEncoder<'a, 'buf>>
+        + AbiHash;
     /// The type returned by this call on error.
-    type ErrorType: SyscallEncodable<'a, Abi, Abi::SyscallRetType, Abi::RetEncoder<'a>>;
+    type ErrorType: for<'buf> SyscallEncodable<'a, Abi, Abi::SyscallRetType, Abi::RetEncoder<'a, 'buf>>
+        + AbiHash;
+    /// A hash of the argument, return, and error types and the version of this syscall. See [AbiHash] and
+    /// [SyscallAbi::CHECK_FINGERPRINTS].
+    const FINGERPRINT: u64 = hash_u64(
+        hash_u64(
+            hash_u64(Self::ABI_HASH, Self::ReturnType::ABI_HASH),
+            Self::ErrorType::ABI_HASH,
+        ),
+        Self::VERSION as u64,
+    );
 
-    /// Perform the syscall with the given ABI.
+    /// What [Self::perform_call] does when the kernel reports that this syscall was interrupted and may be
+    /// restarted.
+    const RESTART_POLICY: RestartPolicy = RestartPolicy::ReturnToCaller;
+
+    /// How many times [Self::perform_call] restarts this syscall under [RestartPolicy::Restart] or
+    /// [RestartPolicy::RestartAdjusted] before returning the interruption to the caller, so that a kernel that
+    /// keeps interrupting the call can't make it loop forever.
+    const MAX_RESTARTS: u32 = 64;
+
+    /// The arguments to restart this syscall with under [RestartPolicy::RestartAdjusted], given the state saved
+    /// by the kernel's handler, e.g. with a timeout shortened by the time already spent waiting. Returning None
+    /// returns the interruption to the caller instead.
+    fn restart_args(&self, state: u64) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        let _ = state;
+        None
+    }
+
+    /// Perform the syscall with the given ABI, restarting it according to [Self::RESTART_POLICY] if it is
+    /// interrupted.
     fn perform_call(
         &self,
         abi: &'a Abi,
     ) -> Result<Self::ReturnType, SyscallError<Self::ErrorType>> {
-        let layout = Layout::new::<Self>();
-        abi.with_alloc(layout, |alloc| {
-            let mut encoder = abi.arg_encoder(alloc);
-            encoder
-                .encode(self)
-                .map_err(SyscallError::<Self::ErrorType>::from)?;
-            let args = encoder.finish();
-
-            // Safety: NUM and args go together by definition.
-            let result = unsafe { abi.syscall_impl(Self::NUM, args) };
-
-            let mut decoder = abi.ret_decoder(result);
-            let result: Result<Self::ReturnType, Self::ErrorType> = decoder
-                .decode()
-                .map_err(SyscallError::<Self::ErrorType>::from)?;
-            result.map_err(SyscallError::SyscallError)
-        })
+        self.perform_call_with(abi, &mut AbiSpill(abi))
+    }
+
+    /// Like [Self::perform_call], but arguments that spill out of registers are encoded into buf, rather than
+    /// memory from [SyscallAbi::with_alloc]. Fails with [SyscallError::BufferTooSmall] if buf is too small.
+    fn perform_call_in(
+        &self,
+        abi: &'a Abi,
+        buf: &mut [u8],
+    ) -> Result<Self::ReturnType, SyscallError<Self::ErrorType>> {
+        self.perform_call_with(abi, buf)
+    }
+
+    /// Like [Self::perform_call], but arguments that spill out of registers are encoded into memory from spill,
+    /// e.g. a [crate::spill::ScratchBuffer].
+    fn perform_call_with<S: SpillAllocator + ?Sized>(
+        &self,
+        abi: &'a Abi,
+        spill: &mut S,
+    ) -> Result<Self::ReturnType, SyscallError<Self::ErrorType>> {
+        let mut args = *self;
+        let mut restarts = 0;
+        loop {
+            let state = match perform_call_once(&args, abi, spill) {
+                Err(SyscallError::Restart(state)) => state,
+                res => return res,
+            };
+            if restarts >= Self::MAX_RESTARTS {
+                return Err(SyscallError::Restart(state));
+            }
+            restarts += 1;
+            match Self::RESTART_POLICY {
+                RestartPolicy::ReturnToCaller => return Err(SyscallError::Restart(state)),
+                RestartPolicy::Restart => {}
+                RestartPolicy::RestartAdjusted => match args.restart_args(state) {
+                    Some(new_args) => args = new_args,
+                    None => return Err(SyscallError::Restart(state)),
+                },
+            }
+        }
     }
 
     /// Used by the table API. You probably don't want to call this directly.
@@ -51,7 +111,35 @@ pub trait SyscallApi<'a, Abi: SyscallAbi + 'a>:
     /// # Safety
     /// Caller must ensure that num and args were received by a syscall together.
     unsafe fn with<
-        F: FnOnce(Abi::SyscallNumType, Self) -> Result<Self::ReturnType, Self::ErrorType>,
+        F: FnOnce(
+            Abi::SyscallNumType,
+            Self,
+        ) -> Result<Self::ReturnType, SyscallError<Self::ErrorType>>,
+    >(
+        abi: &'a Abi,
+        num: Abi::SyscallNumType,
+        args: Abi::SyscallArgType,
+        call: F,
+    ) -> Result<Self::ReturnType, SyscallError<Self::ErrorType>>
+    where
+        Self: Sized,
+    {
+        let me = Self::decode_call(abi, num, args)?;
+        (call)(num, me)
+    }
+
+    /// Used by the table API. Like [Self::with], but decodes the arguments as an older version of this
+    /// syscall, and upgrades them to this version before calling.
+    ///
+    /// # Safety
+    /// Caller must ensure that num and args were received by a syscall together, and that the syscall was
+    /// made with Old's version.
+    unsafe fn with_upgraded<
+        Old: SyscallApi<'a, Abi> + Into<Self>,
+        F: FnOnce(
+            Abi::SyscallNumType,
+            Self,
+        ) -> Result<Self::ReturnType, SyscallError<Self::ErrorType>>,
     >(
         abi: &'a Abi,
         num: Abi::SyscallNumType,
@@ -61,11 +149,122 @@ pub trait SyscallApi<'a, Abi: SyscallAbi + 'a>:
     where
         Self: Sized,
     {
-        let mut arg_decoder = abi.arg_decoder(args);
-        let me = Self::decode(&mut arg_decoder)?;
-        let res = (call)(num, me);
-        res.map_err(SyscallError::SyscallError)
+        let me = Self::decode_upgraded_call::<Old>(abi, num, args)?;
+        (call)(num, me)
     }
+
+    /// Used by the table API. Decodes the arguments of a call, for tables that call their handlers
+    /// themselves.
+    ///
+    /// # Safety
+    /// Caller must ensure that num and args were received by a syscall together.
+    unsafe fn decode_call(
+        abi: &'a Abi,
+        num: Abi::SyscallNumType,
+        args: Abi::SyscallArgType,
+    ) -> Result<Self, SyscallError<Self::ErrorType>>
+    where
+        Self: Sized,
+    {
+        decode_args(abi, num, args, Self::FINGERPRINT)
+    }
+
+    /// Used by the table API. Like [Self::decode_call], but decodes the arguments as an older version of this
+    /// syscall, and upgrades them to this version.
+    ///
+    /// # Safety
+    /// Caller must ensure that num and args were received by a syscall together, and that the syscall was
+    /// made with Old's version.
+    unsafe fn decode_upgraded_call<Old: SyscallApi<'a, Abi> + Into<Self>>(
+        abi: &'a Abi,
+        num: Abi::SyscallNumType,
+        args: Abi::SyscallArgType,
+    ) -> Result<Self, SyscallError<Self::ErrorType>>
+    where
+        Self: Sized,
+    {
+        let old: Old = decode_args(abi, num, args, Old::FINGERPRINT)?;
+        Ok(old.into())
+    }
+}
+
+fn perform_call_once<'a, Abi: SyscallAbi, T: SyscallApi<'a, Abi>, S: SpillAllocator + ?Sized>(
+    args: &T,
+    abi: &'a Abi,
+    spill: &mut S,
+) -> Result<T::ReturnType, SyscallError<T::ErrorType>> {
+    let layout = if Abi::CHECK_FINGERPRINTS {
+        Layout::new::<(u64, T)>()
+    } else {
+        Layout::new::<T>()
+    };
+    spill.with_spill(layout, |alloc| {
+        let mut encoder = abi.arg_encoder(alloc);
+        if Abi::CHECK_FINGERPRINTS {
+            encoder
+                .encode(&T::FINGERPRINT)
+                .map_err(SyscallError::<T::ErrorType>::from)?;
+        }
+        encoder
+            .encode(args)
+            .map_err(SyscallError::<T::ErrorType>::from)?;
+        let args = encoder.finish();
+
+        // Safety: NUM and args go together by definition.
+        let result = unsafe { abi.syscall_impl(abi.versioned_num(T::NUM, T::VERSION), args) };
+
+        let mut decoder = abi.ret_decoder(result);
+        // The kernel table encodes errors as SyscallErrors, so that it can report decoding and
+        // version errors as well as the syscall's own.
+        let result: Result<T::ReturnType, SyscallError<T::ErrorType>> =
+            decoder.decode().map_err(|e| {
+                let e = decoder.record_decode_error(None, e);
+                if let Some(context) = decoder.decode_context() {
+                    abi.decode_failed(context.with_num(T::NUM));
+                }
+                SyscallError::<T::ErrorType>::from(e)
+            })?;
+        result
+    })
+}
+
+fn decode_args<'a, Abi: SyscallAbi, T, E: Copy>(
+    abi: &'a Abi,
+    num: Abi::SyscallNumType,
+    args: Abi::SyscallArgType,
+    fingerprint: u64,
+) -> Result<T, SyscallError<E>>
+where
+    T: SyscallEncodable<'a, Abi, Abi::SyscallArgType, Abi::ArgEncoder<'a, 'static>>,
+{
+    let mut arg_decoder = abi.arg_decoder(args);
+    let mut decode = || {
+        if Abi::CHECK_FINGERPRINTS && arg_decoder.decode::<u64>()? != fingerprint {
+            return Ok(Err(SyscallError::AbiMismatch));
+        }
+        T::decode(&mut arg_decoder).map(Ok)
+    };
+    decode().map_err(|e| {
+        let e = arg_decoder.record_decode_error(None, e);
+        if let Some(context) = arg_decoder.decode_context() {
+            abi.decode_failed(context.with_num(num));
+        }
+        SyscallError::from(e)
+    })?
+}
+
+/// What [SyscallApi::perform_call] does when the kernel reports that a call was interrupted, e.g. by a signal
+/// or upcall, and may be restarted ([SyscallError::Restart]). Calls that the kernel reports as
+/// [SyscallError::Interrupted] are never restarted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RestartPolicy {
+    /// Return the interruption to the caller.
+    ReturnToCaller,
+    /// Restart the call with the same arguments, up to [SyscallApi::MAX_RESTARTS] times.
+    Restart,
+    /// Restart the call with the arguments returned by [SyscallApi::restart_args], up to
+    /// [SyscallApi::MAX_RESTARTS] times.
+    RestartAdjusted,
 }
 
 /// Indicates that a type may be encoded using an encoder, and implements the method for encoding.
@@ -79,7 +79,7 @@ where
         match decoder.decode_u8()? {
             0 => Ok(false),
             1 => Ok(true),
-            _ => Err(DecodeError::InvalidData),
+            _ => Err(DecodeError::BadBool),
         }
     }
 }
@@ -128,7 +128,7 @@ where
         Ok(match dis {
             0 => Ok(T::decode(decoder)?),
             1 => Err(E::decode(decoder)?),
-            _ => return Err(DecodeError::InvalidData),
+            _ => return Err(DecodeError::BadDiscriminant),
         })
     }
 }
@@ -157,7 +157,7 @@ where
         Ok(match dis {
             0 => None,
             1 => Some(T::decode(decoder)?),
-            _ => return Err(DecodeError::InvalidData),
+            _ => return Err(DecodeError::BadDiscriminant),
         })
     }
 }
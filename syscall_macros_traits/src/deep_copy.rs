@@ -0,0 +1,285 @@
+use core::{alloc::Layout, marker::PhantomData};
+
+use crate::{
+    inline::{InlineBytes, InlineStr},
+    iovec::{IoVec, UserIoVec},
+    ptr::{UserPointer, UserSlice, UserStr},
+};
+
+/// Errors that occur while deep-copying user memory into the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Ord, Eq, Hash)]
+pub enum DeepCopyError {
+    /// A pointer was misaligned, or the verification closure rejected the memory.
+    InvalidPointer,
+    /// The structure is nested deeper than the maximum depth.
+    TooDeep,
+    /// The structure does not fit in the kernel buffer.
+    TooLarge,
+    /// A copied value broke an invariant of its type, e.g. an inline string that is not valid UTF-8.
+    InvalidData,
+}
+
+/// Describes the user references (e.g. [UserPointer] and [UserSlice]) embedded in a type, so that the kernel can
+/// validate and copy an entire structure at once. May be derived for structs whose fields are all UserCopy.
+///
+/// User memory is copied into the kernel as raw bytes, so this is only implemented for plain integers, and arrays
+/// and structs of them. Types such as bool, enums and references, which have invalid bit patterns, are not UserCopy,
+/// and should be passed as integers or decoded with [crate::api::SyscallEncodable::decode] instead.
+///
+/// # Safety
+/// Every bit pattern of Self's size must be a valid value of Self. Invariants beyond that, such as an inline
+/// string holding UTF-8, must be checked by [Self::copy_refs], which runs before the copy is exposed.
+pub unsafe trait UserCopy: Copy {
+    /// Copy the user memory referenced by self into the context's buffer, and rewrite the references in self to
+    /// point at the copies, after checking any invariants of Self. Types without embedded references or
+    /// invariants should do nothing.
+    fn copy_refs<F: FnMut(*const u8, usize) -> bool>(
+        &mut self,
+        ctx: &mut DeepCopy<'_, F>,
+    ) -> Result<(), DeepCopyError>;
+}
+
+/// State for a deep copy of user memory into a kernel buffer. Tracks the space used in the buffer, the current
+/// depth, and the verification closure (see [UserPointer::as_ref]).
+pub struct DeepCopy<'k, F> {
+    buf: *mut u8,
+    size: usize,
+    used: usize,
+    depth: usize,
+    max_depth: usize,
+    f: F,
+    _pd: PhantomData<&'k mut [u8]>,
+}
+
+impl<'k, F: FnMut(*const u8, usize) -> bool> DeepCopy<'k, F> {
+    /// Create a new deep copy context. The total size of the copy is limited by the length of buf, and the number
+    /// of pointers that may be followed from the root by max_depth.
+    pub fn new(buf: &'k mut [u8], max_depth: usize, f: F) -> Self {
+        Self {
+            buf: buf.as_mut_ptr(),
+            size: buf.len(),
+            used: 0,
+            depth: 0,
+            max_depth,
+            f,
+            _pd: PhantomData,
+        }
+    }
+
+    /// The number of bytes of the kernel buffer used so far.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    fn alloc(&mut self, layout: Layout) -> Result<*mut u8, DeepCopyError> {
+        // Safety: used never exceeds size.
+        let start = unsafe { self.buf.add(self.used) };
+        let pad = start.align_offset(layout.align());
+        let end = self
+            .used
+            .checked_add(pad)
+            .and_then(|x| x.checked_add(layout.size()))
+            .ok_or(DeepCopyError::TooLarge)?;
+        if end > self.size {
+            return Err(DeepCopyError::TooLarge);
+        }
+        // Safety: we checked that the allocation fits within the buffer.
+        let ptr = unsafe { start.add(pad) };
+        self.used = end;
+        Ok(ptr)
+    }
+
+    /// Verify and copy len values of type T from the user address addr into the kernel buffer, and then copy
+    /// any references in those values. Returns the kernel address of the copy.
+    pub fn copy_in<T: UserCopy>(
+        &mut self,
+        addr: usize,
+        len: usize,
+    ) -> Result<*mut T, DeepCopyError> {
+        if self.depth >= self.max_depth {
+            return Err(DeepCopyError::TooDeep);
+        }
+        let src: *const T = core::ptr::from_exposed_addr(addr);
+        if src.is_null() || !src.is_aligned() {
+            return Err(DeepCopyError::InvalidPointer);
+        }
+        let layout = Layout::array::<T>(len).map_err(|_| DeepCopyError::TooLarge)?;
+        if !(self.f)(src as *const u8, layout.size()) {
+            return Err(DeepCopyError::InvalidPointer);
+        }
+        let dst = self.alloc(layout)? as *mut T;
+        // Safety: the source was verified, and the destination was allocated with the right layout. The buffer
+        // is exclusively borrowed by this context, so they cannot overlap.
+        unsafe { core::ptr::copy_nonoverlapping(src, dst, len) };
+
+        self.depth += 1;
+        let res = (0..len).try_for_each(|i| {
+            // Safety: the value was just copied into the buffer, and no other reference to it exists.
+            unsafe { (*dst.add(i)).copy_refs(self) }
+        });
+        self.depth -= 1;
+        res.map(|_| dst)
+    }
+}
+
+impl<'abi, T: UserCopy> UserPointer<'abi, T> {
+    /// Verify and copy the value pointed to, and everything it references, into buf. References within the
+    /// returned value point into buf. Nesting is limited by max_depth, and the total size by the length of buf.
+    /// See [UserPointer::as_ref] for the verification closure, which is called for every region copied.
+    pub fn deep_copy_in<'k>(
+        &self,
+        buf: &'k mut [u8],
+        max_depth: usize,
+        f: impl FnMut(*const u8, usize) -> bool,
+    ) -> Result<&'k T, DeepCopyError> {
+        let mut ctx = DeepCopy::new(buf, max_depth, f);
+        let ptr = ctx.copy_in::<T>(self.raw, 1)?;
+        // Safety: the value was copied into buf, which we borrow for 'k.
+        Ok(unsafe { &*ptr })
+    }
+}
+
+impl<'abi, T: UserCopy> UserSlice<'abi, T> {
+    /// Verify and copy the slice, and everything it references, into buf. See [UserPointer::deep_copy_in].
+    pub fn deep_copy_in<'k>(
+        &self,
+        buf: &'k mut [u8],
+        max_depth: usize,
+        f: impl FnMut(*const u8, usize) -> bool,
+    ) -> Result<&'k [T], DeepCopyError> {
+        let mut ctx = DeepCopy::new(buf, max_depth, f);
+        let ptr = ctx.copy_in::<T>(self.ptr.raw, self.len)?;
+        // Safety: the values were copied into buf, which we borrow for 'k.
+        Ok(unsafe { core::slice::from_raw_parts(ptr, self.len) })
+    }
+}
+
+macro_rules! impl_no_refs {
+    ($($ty:ty),*) => {
+        $(
+            // Safety: every bit pattern is a valid integer.
+            unsafe impl UserCopy for $ty {
+                fn copy_refs<F: FnMut(*const u8, usize) -> bool>(
+                    &mut self,
+                    _ctx: &mut DeepCopy<'_, F>,
+                ) -> Result<(), DeepCopyError> {
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_no_refs!(
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    ()
+);
+
+// Safety: InlineBytes is a length and an array of bytes. The length is checked below.
+unsafe impl<const N: usize> UserCopy for InlineBytes<N> {
+    fn copy_refs<F: FnMut(*const u8, usize) -> bool>(
+        &mut self,
+        _ctx: &mut DeepCopy<'_, F>,
+    ) -> Result<(), DeepCopyError> {
+        if self.len > N {
+            return Err(DeepCopyError::InvalidData);
+        }
+        Ok(())
+    }
+}
+
+// Safety: InlineStr is an InlineBytes. Its contents are validated as UTF-8 below, as on decode.
+unsafe impl<const N: usize> UserCopy for InlineStr<N> {
+    fn copy_refs<F: FnMut(*const u8, usize) -> bool>(
+        &mut self,
+        ctx: &mut DeepCopy<'_, F>,
+    ) -> Result<(), DeepCopyError> {
+        self.bytes.copy_refs(ctx)?;
+        core::str::from_utf8(self.bytes.as_bytes()).map_err(|_| DeepCopyError::InvalidData)?;
+        Ok(())
+    }
+}
+
+// Safety: an array is valid if its elements are.
+unsafe impl<T: UserCopy, const N: usize> UserCopy for [T; N] {
+    fn copy_refs<F: FnMut(*const u8, usize) -> bool>(
+        &mut self,
+        ctx: &mut DeepCopy<'_, F>,
+    ) -> Result<(), DeepCopyError> {
+        self.iter_mut().try_for_each(|item| item.copy_refs(ctx))
+    }
+}
+
+// Safety: user pointers are plain addresses.
+unsafe impl<'abi, T: UserCopy> UserCopy for UserPointer<'abi, T> {
+    fn copy_refs<F: FnMut(*const u8, usize) -> bool>(
+        &mut self,
+        ctx: &mut DeepCopy<'_, F>,
+    ) -> Result<(), DeepCopyError> {
+        // Null pointers are left null, for the kernel to interpret.
+        if self.is_null() {
+            return Ok(());
+        }
+        self.raw = ctx.copy_in::<T>(self.raw, 1)?.expose_addr();
+        Ok(())
+    }
+}
+
+// Safety: user slices are a user pointer and a length.
+unsafe impl<'abi, T: UserCopy> UserCopy for UserSlice<'abi, T> {
+    fn copy_refs<F: FnMut(*const u8, usize) -> bool>(
+        &mut self,
+        ctx: &mut DeepCopy<'_, F>,
+    ) -> Result<(), DeepCopyError> {
+        if self.ptr.is_null() {
+            return Ok(());
+        }
+        self.ptr.raw = ctx.copy_in::<T>(self.ptr.raw, self.len)?.expose_addr();
+        Ok(())
+    }
+}
+
+// Safety: a user string is a user slice. Like UserStr::copy_into, its contents are validated when they are used.
+unsafe impl<'abi> UserCopy for UserStr<'abi> {
+    fn copy_refs<F: FnMut(*const u8, usize) -> bool>(
+        &mut self,
+        ctx: &mut DeepCopy<'_, F>,
+    ) -> Result<(), DeepCopyError> {
+        self.bytes.copy_refs(ctx)
+    }
+}
+
+// Safety: an iovec is an address and a length.
+unsafe impl<'abi> UserCopy for IoVec<'abi> {
+    fn copy_refs<F: FnMut(*const u8, usize) -> bool>(
+        &mut self,
+        ctx: &mut DeepCopy<'_, F>,
+    ) -> Result<(), DeepCopyError> {
+        if self.base == 0 {
+            return Ok(());
+        }
+        self.base = ctx.copy_in::<u8>(self.base, self.len)?.expose_addr();
+        Ok(())
+    }
+}
+
+// Safety: a user iovec is a user slice.
+unsafe impl<'abi> UserCopy for UserIoVec<'abi> {
+    fn copy_refs<F: FnMut(*const u8, usize) -> bool>(
+        &mut self,
+        ctx: &mut DeepCopy<'_, F>,
+    ) -> Result<(), DeepCopyError> {
+        self.iovs.copy_refs(ctx)
+    }
+}
@@ -1,14 +1,23 @@
+use core::fmt::Display;
+
 use crate::{
     abi::{Allocation, SyscallAbi},
     api::SyscallEncodable,
 };
 
+pub mod context;
+
+use context::DecodeContext;
+
 /// Defines how an encoder works.
 pub trait SyscallEncoder<'a, Abi: SyscallAbi + ?Sized, EncodedType: Copy> {
     /// Make a new encoder for decoding data.
     fn new_decode(abi: &'a Abi, decode_data: EncodedType) -> Self;
-    /// Make a new encoder given this allocation.
-    fn new_encode(abi: &'a Abi, allocation: Allocation) -> Self;
+    /// Get the ABI this encoder was created with, if it keeps it. Needed to encode pointers that the ABI
+    /// translates, such as a local [crate::ptr::ObjectPointer]. Returns None by default.
+    fn abi(&self) -> Option<&Abi> {
+        None
+    }
     /// Called by SyscallEncodable when derived.
     fn size_hint(&mut self, _size: usize) {}
     /// Encode an item.
@@ -37,6 +46,30 @@ pub trait SyscallEncoder<'a, Abi: SyscallAbi + ?Sized, EncodedType: Copy> {
 
     /// Finish encoding and return the EncodedType.
     fn finish(self) -> EncodedType;
+
+    /// Called by derived implementations as a decode error propagates out of a field (or, with None, when
+    /// the error originates in the derived type itself, e.g. a bad discriminant). Encoders that keep a
+    /// [DecodeContext] record the field here. Returns the error to propagate.
+    fn record_decode_error(
+        &mut self,
+        _field: Option<&'static str>,
+        err: DecodeError,
+    ) -> DecodeError {
+        err
+    }
+
+    /// Get the context of the first decode error recorded by this encoder, if it keeps one.
+    fn decode_context(&self) -> Option<DecodeContext> {
+        None
+    }
+}
+
+/// Creates encoders that encode into an allocation borrowed for 'buf, which the encoder cannot outlive. 'buf is
+/// independent of 'a, the lifetime of the values being encoded, so that an encoder can be made for memory that
+/// lives only as long as a single call.
+pub trait AllocEncoder<'a, 'buf, Abi: SyscallAbi> {
+    /// Make a new encoder given this allocation.
+    fn new_encode(abi: &'a Abi, allocation: Allocation<'buf>) -> Self;
 }
 
 /// Errors that occur during encoding.
@@ -46,6 +79,8 @@ pub enum EncodeError {
     AllocationError,
     /// Failed to encode a value.
     PrimitiveError,
+    /// A local address could not be translated by the ABI, see [SyscallAbi::addr_to_object].
+    UntranslatableAddress,
 }
 
 /// Errors that occur during decoding.
@@ -53,6 +88,24 @@ pub enum EncodeError {
 pub enum DecodeError {
     /// Data was impossible to decode into the supplied type.
     InvalidData,
-    /// The number of the syscall was not recognized, or a discriminant was invalid.
+    /// The number of the syscall was not recognized.
     InvalidNum,
+    /// An enum discriminant was not recognized.
+    BadDiscriminant,
+    /// A bool was neither 0 nor 1.
+    BadBool,
+    /// The spilled part of the encoded data could not be accessed.
+    SpillFault,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            DecodeError::InvalidData => "invalid data",
+            DecodeError::InvalidNum => "invalid syscall number",
+            DecodeError::BadDiscriminant => "bad discriminant",
+            DecodeError::BadBool => "bad bool",
+            DecodeError::SpillFault => "fault reading spilled data",
+        })
+    }
 }
@@ -0,0 +1,159 @@
+use core::fmt::Display;
+
+use super::DecodeError;
+
+/// The maximum number of fields recorded in a [FieldPath]. Deeper paths keep the innermost fields.
+pub const MAX_FIELD_DEPTH: usize = 8;
+
+/// The path of fields that a decoder was in when decoding failed, e.g. `args.flags`. Recorded by
+/// derived implementations as the error propagates out, so it costs nothing when decoding succeeds.
+/// With the `compact-errors` feature, no fields are recorded and this type is zero-sized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FieldPath {
+    #[cfg(not(feature = "compact-errors"))]
+    names: [&'static str; MAX_FIELD_DEPTH],
+    #[cfg(not(feature = "compact-errors"))]
+    len: u8,
+    #[cfg(not(feature = "compact-errors"))]
+    truncated: bool,
+}
+
+impl FieldPath {
+    /// Record an enclosing field. Fields are pushed innermost first.
+    pub fn push_outer(&mut self, _name: &'static str) {
+        #[cfg(not(feature = "compact-errors"))]
+        {
+            if (self.len as usize) < MAX_FIELD_DEPTH {
+                self.names[self.len as usize] = _name;
+                self.len += 1;
+            } else {
+                self.truncated = true;
+            }
+        }
+    }
+
+    /// Iterate over the recorded fields, outermost first.
+    pub fn iter(&self) -> impl Iterator<Item = &'static str> + '_ {
+        #[cfg(not(feature = "compact-errors"))]
+        return self.names[..self.len as usize].iter().rev().copied();
+        #[cfg(feature = "compact-errors")]
+        return core::iter::empty();
+    }
+
+    /// Get the number of recorded fields.
+    pub fn len(&self) -> usize {
+        #[cfg(not(feature = "compact-errors"))]
+        return self.len as usize;
+        #[cfg(feature = "compact-errors")]
+        return 0;
+    }
+
+    /// Is the path empty?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Were outer fields dropped because the path was deeper than [MAX_FIELD_DEPTH]?
+    pub fn is_truncated(&self) -> bool {
+        #[cfg(not(feature = "compact-errors"))]
+        return self.truncated;
+        #[cfg(feature = "compact-errors")]
+        return false;
+    }
+}
+
+impl Display for FieldPath {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.is_truncated() {
+            f.write_str("...")?;
+        }
+        for (i, name) in self.iter().enumerate() {
+            if i > 0 || self.is_truncated() {
+                f.write_str(".")?;
+            }
+            f.write_str(name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where and why decoding failed. Encoders that support it record this as a decode error propagates,
+/// see [super::SyscallEncoder::record_decode_error]. With the `compact-errors` feature, only the
+/// error kind and syscall number are kept. Encoders don't know which syscall they are decoding, so
+/// they record a context with no number, which is given the syscall's number with [Self::with_num]
+/// before it is passed to [crate::abi::SyscallAbi::decode_failed].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DecodeContext<Num: Copy = ()> {
+    num: Num,
+    error: DecodeError,
+    #[cfg(not(feature = "compact-errors"))]
+    offset: usize,
+    path: FieldPath,
+}
+
+impl DecodeContext {
+    /// Create a new context for an error that occurred at the given byte offset into the encoded data.
+    pub fn new(error: DecodeError, _offset: usize) -> Self {
+        Self {
+            num: (),
+            error,
+            #[cfg(not(feature = "compact-errors"))]
+            offset: _offset,
+            path: FieldPath::default(),
+        }
+    }
+}
+
+impl<Num: Copy> DecodeContext<Num> {
+    /// Attribute this context to syscall num.
+    pub fn with_num<N: Copy>(self, num: N) -> DecodeContext<N> {
+        DecodeContext {
+            num,
+            error: self.error,
+            #[cfg(not(feature = "compact-errors"))]
+            offset: self.offset,
+            path: self.path,
+        }
+    }
+
+    /// The number of the syscall whose data failed to decode.
+    pub fn num(&self) -> Num {
+        self.num
+    }
+
+    /// The kind of error that occurred.
+    pub fn error(&self) -> DecodeError {
+        self.error
+    }
+
+    /// The byte offset into the encoded data at which the error occurred, if recorded.
+    pub fn offset(&self) -> Option<usize> {
+        #[cfg(not(feature = "compact-errors"))]
+        return Some(self.offset);
+        #[cfg(feature = "compact-errors")]
+        return None;
+    }
+
+    /// The path of fields that were being decoded when the error occurred.
+    pub fn path(&self) -> &FieldPath {
+        &self.path
+    }
+
+    /// Record an enclosing field, see [FieldPath::push_outer].
+    pub fn push_field(&mut self, name: &'static str) {
+        self.path.push_outer(name)
+    }
+}
+
+impl<Num: Copy> Display for DecodeContext<Num> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.error)?;
+        if !self.path.is_empty() {
+            write!(f, " in field {}", self.path)?;
+        }
+        if let Some(offset) = self.offset() {
+            write!(f, " at byte {}", offset)?;
+        }
+        Ok(())
+    }
+}
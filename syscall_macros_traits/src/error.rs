@@ -1,9 +1,92 @@
+use core::fmt::{Debug, Display};
+
 use crate::{
     abi::SyscallAbi,
     api::SyscallEncodable,
     encoder::{DecodeError, EncodeError, SyscallEncoder},
+    fingerprint::{hash_str, hash_u64, AbiHash, HASH_SEED},
 };
 
+pub mod errno;
+
+/// Assigns stable numeric codes and messages to an error type, so that errors can be reported without the
+/// encoder (e.g. to C callers, or in traces and logs). May be derived, with a `#[code = N]` attribute on
+/// every variant, and optional `#[message = "..."]` and `#[errno = N]` attributes.
+pub trait ErrorCode: Copy {
+    /// The stable numeric code for this error.
+    fn code(&self) -> u32;
+    /// Get the error for a numeric code, if there is one.
+    fn from_code(code: u32) -> Option<Self>;
+    /// A human-readable message for this error.
+    fn message(&self) -> &'static str;
+    /// The Linux errno value that best matches this error, if any. See [errno].
+    fn errno(&self) -> Option<i32> {
+        None
+    }
+}
+
+/// Raw code for [SyscallError::InvalidData]. See [SyscallError::into_raw].
+pub const RAW_INVALID_DATA: i64 = -1;
+/// Raw code for [SyscallError::InvalidNum].
+pub const RAW_INVALID_NUM: i64 = -2;
+/// Raw code for [SyscallError::AllocationError].
+pub const RAW_ALLOCATION_ERROR: i64 = -3;
+/// Raw code for [SyscallError::VersionTooOld].
+pub const RAW_VERSION_TOO_OLD: i64 = -4;
+/// Raw code for [SyscallError::VersionTooNew].
+pub const RAW_VERSION_TOO_NEW: i64 = -5;
+/// Raw code for [SyscallError::AbiMismatch].
+pub const RAW_ABI_MISMATCH: i64 = -6;
+/// Raw code for [SyscallError::Interrupted].
+pub const RAW_INTERRUPTED: i64 = -7;
+/// Raw code for [SyscallError::Restart]. The restart state is not preserved.
+pub const RAW_RESTART: i64 = -8;
+/// Raw code for [SyscallError::BufferTooSmall]. The needed size is not preserved.
+pub const RAW_BUFFER_TOO_SMALL: i64 = -9;
+/// Raw codes for [SyscallError::DecodeFailed] are the negated kind of decode error, offset by this value.
+pub const RAW_DECODE_FAILED_BASE: i64 = 0xa;
+/// Raw codes for [SyscallError::Unknown] are the negated discriminant, offset by this value.
+pub const RAW_UNKNOWN_BASE: i64 = 0x10;
+/// Raw codes for [SyscallError::SyscallError] are the negated error code, offset by this value, which leaves room
+/// for every unknown discriminant above [RAW_UNKNOWN_BASE].
+pub const RAW_ERROR_BASE: i64 = RAW_UNKNOWN_BASE + 0x100;
+/// The code reported for the catch-all Unknown variant of a derived `#[syscall(non_exhaustive)]`
+/// error type. Never returned by [ErrorCode::from_code].
+pub const UNKNOWN_CODE: u32 = u32::MAX;
+
+// Discriminants used when encoding a SyscallError.
+const DISC_INVALID_DATA: u8 = 0;
+const DISC_INVALID_NUM: u8 = 1;
+const DISC_ALLOCATION_ERROR: u8 = 2;
+const DISC_SYSCALL_ERROR: u8 = 3;
+const DISC_VERSION_TOO_OLD: u8 = 4;
+const DISC_VERSION_TOO_NEW: u8 = 5;
+const DISC_ABI_MISMATCH: u8 = 6;
+const DISC_INTERRUPTED: u8 = 7;
+const DISC_RESTART: u8 = 8;
+const DISC_BUFFER_TOO_SMALL: u8 = 9;
+const DISC_DECODE_FAILED: u8 = 10;
+
+// Codes used when encoding the DecodeError of a SyscallError::DecodeFailed.
+const DECODE_ERRORS: [DecodeError; 5] = [
+    DecodeError::InvalidData,
+    DecodeError::InvalidNum,
+    DecodeError::BadDiscriminant,
+    DecodeError::BadBool,
+    DecodeError::SpillFault,
+];
+
+fn decode_error_code(err: DecodeError) -> u8 {
+    DECODE_ERRORS.iter().position(|e| *e == err).unwrap_or(0) as u8
+}
+
+fn decode_error_from_code(code: u8) -> DecodeError {
+    DECODE_ERRORS
+        .get(code as usize)
+        .copied()
+        .unwrap_or(DecodeError::InvalidData)
+}
+
 /// Error returning from a syscall. SyscallError comes from errors
 /// from the call itself, whereas the other values indicate errors during
 /// encode or decode.
@@ -13,13 +96,156 @@ pub enum SyscallError<Err: Copy> {
     InvalidNum,
     AllocationError,
     SyscallError(Err),
+    /// The kernel no longer supports the version of the syscall that was called.
+    VersionTooOld,
+    /// The kernel does not yet support the version of the syscall that was called.
+    VersionTooNew,
+    /// The caller was built against a different definition of the syscall's types than the kernel. See
+    /// [SyscallAbi::CHECK_FINGERPRINTS].
+    AbiMismatch,
+    /// The call was interrupted, e.g. by a signal or upcall, before it completed, and must not be restarted.
+    Interrupted,
+    /// The call was interrupted before it completed, and may be restarted. Carries state saved by the
+    /// handler, which the caller may pass back when restarting. See [crate::api::RestartPolicy].
+    Restart(u64),
+    /// The buffer given for the call's arguments to spill into was too small, holding the number of bytes
+    /// needed. See [crate::api::SyscallApi::perform_call_in].
+    BufferTooSmall(usize),
+    /// Data that passed the encoder's checks could not be decoded, e.g. because of a bad enum discriminant or
+    /// bool, or a fault reading spilled data. Holds the kind of decode error.
+    DecodeFailed(DecodeError),
+    /// An error with a discriminant that this version does not know about, e.g. one added by a newer
+    /// kernel. The raw discriminant is preserved. SyscallError's discriminant is encoded as a u8, so unlike the
+    /// `Unknown(u64)` variant of a derived non-exhaustive enum, this holds a u8.
+    Unknown(u8),
+}
+
+impl<Err: Copy> SyscallError<Err> {
+    /// Convert the error returned by the syscall itself with f, keeping any other error as is. Used to
+    /// unwrap one command's error from the error of a multiplexed syscall.
+    pub fn map_syscall_error<F: Copy>(
+        self,
+        f: impl FnOnce(Err) -> SyscallError<F>,
+    ) -> SyscallError<F> {
+        match self {
+            SyscallError::InvalidData => SyscallError::InvalidData,
+            SyscallError::InvalidNum => SyscallError::InvalidNum,
+            SyscallError::AllocationError => SyscallError::AllocationError,
+            SyscallError::SyscallError(e) => f(e),
+            SyscallError::VersionTooOld => SyscallError::VersionTooOld,
+            SyscallError::VersionTooNew => SyscallError::VersionTooNew,
+            SyscallError::AbiMismatch => SyscallError::AbiMismatch,
+            SyscallError::Interrupted => SyscallError::Interrupted,
+            SyscallError::Restart(state) => SyscallError::Restart(state),
+            SyscallError::BufferTooSmall(needed) => SyscallError::BufferTooSmall(needed),
+            SyscallError::DecodeFailed(err) => SyscallError::DecodeFailed(err),
+            SyscallError::Unknown(dis) => SyscallError::Unknown(dis),
+        }
+    }
+}
+
+impl<Err: ErrorCode> SyscallError<Err> {
+    /// Map this error into a single negative integer, suitable for returning in a register. Non-negative
+    /// values are left for successful returns.
+    pub fn into_raw(self) -> i64 {
+        match self {
+            SyscallError::InvalidData => RAW_INVALID_DATA,
+            SyscallError::InvalidNum => RAW_INVALID_NUM,
+            SyscallError::AllocationError => RAW_ALLOCATION_ERROR,
+            SyscallError::SyscallError(e) => -(RAW_ERROR_BASE + e.code() as i64),
+            SyscallError::VersionTooOld => RAW_VERSION_TOO_OLD,
+            SyscallError::VersionTooNew => RAW_VERSION_TOO_NEW,
+            SyscallError::AbiMismatch => RAW_ABI_MISMATCH,
+            SyscallError::Interrupted => RAW_INTERRUPTED,
+            SyscallError::Restart(_) => RAW_RESTART,
+            SyscallError::BufferTooSmall(_) => RAW_BUFFER_TOO_SMALL,
+            SyscallError::DecodeFailed(err) => {
+                -(RAW_DECODE_FAILED_BASE + decode_error_code(err) as i64)
+            }
+            SyscallError::Unknown(dis) => -(RAW_UNKNOWN_BASE + dis as i64),
+        }
+    }
+
+    /// Get the error for a raw value produced by [Self::into_raw], if there is one.
+    pub fn from_raw(raw: i64) -> Option<Self> {
+        match raw {
+            RAW_INVALID_DATA => Some(SyscallError::InvalidData),
+            RAW_INVALID_NUM => Some(SyscallError::InvalidNum),
+            RAW_ALLOCATION_ERROR => Some(SyscallError::AllocationError),
+            RAW_VERSION_TOO_OLD => Some(SyscallError::VersionTooOld),
+            RAW_VERSION_TOO_NEW => Some(SyscallError::VersionTooNew),
+            RAW_ABI_MISMATCH => Some(SyscallError::AbiMismatch),
+            RAW_INTERRUPTED => Some(SyscallError::Interrupted),
+            RAW_RESTART => Some(SyscallError::Restart(0)),
+            RAW_BUFFER_TOO_SMALL => Some(SyscallError::BufferTooSmall(0)),
+            _ => {
+                let raw = raw.checked_neg()?;
+                if raw < RAW_UNKNOWN_BASE {
+                    let code: u8 = raw.checked_sub(RAW_DECODE_FAILED_BASE)?.try_into().ok()?;
+                    return DECODE_ERRORS
+                        .get(code as usize)
+                        .map(|err| SyscallError::DecodeFailed(*err));
+                }
+                if raw < RAW_ERROR_BASE {
+                    let dis: u8 = raw.checked_sub(RAW_UNKNOWN_BASE)?.try_into().ok()?;
+                    return (dis > DISC_DECODE_FAILED).then_some(SyscallError::Unknown(dis));
+                }
+                Err::from_code((raw - RAW_ERROR_BASE).try_into().ok()?)
+                    .map(SyscallError::SyscallError)
+            }
+        }
+    }
+
+    /// The Linux errno value that best matches this error. Errors without an errno mapping are reported as EIO.
+    pub fn errno(&self) -> i32 {
+        match self {
+            SyscallError::InvalidData => errno::EINVAL,
+            SyscallError::InvalidNum => errno::ENOSYS,
+            SyscallError::AllocationError => errno::ENOMEM,
+            SyscallError::SyscallError(e) => e.errno().unwrap_or(errno::EIO),
+            SyscallError::VersionTooOld | SyscallError::VersionTooNew => errno::EOPNOTSUPP,
+            SyscallError::AbiMismatch => errno::EPROTO,
+            SyscallError::Interrupted | SyscallError::Restart(_) => errno::EINTR,
+            SyscallError::BufferTooSmall(_) => errno::ENOBUFS,
+            SyscallError::DecodeFailed(DecodeError::SpillFault) => errno::EFAULT,
+            SyscallError::DecodeFailed(_) => errno::EINVAL,
+            SyscallError::Unknown(_) => errno::EIO,
+        }
+    }
 }
 
+impl<Err: ErrorCode> Display for SyscallError<Err> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SyscallError::InvalidData => f.write_str("invalid syscall data"),
+            SyscallError::InvalidNum => f.write_str("invalid syscall number"),
+            SyscallError::AllocationError => f.write_str("failed to allocate memory for encoding"),
+            SyscallError::SyscallError(e) => f.write_str(e.message()),
+            SyscallError::VersionTooOld => f.write_str("syscall version is no longer supported"),
+            SyscallError::VersionTooNew => f.write_str("syscall version is not yet supported"),
+            SyscallError::AbiMismatch => f.write_str("syscall ABI does not match the kernel"),
+            SyscallError::Interrupted => f.write_str("syscall was interrupted"),
+            SyscallError::Restart(_) => f.write_str("syscall was interrupted and may be restarted"),
+            SyscallError::BufferTooSmall(needed) => {
+                write!(
+                    f,
+                    "syscall spill buffer is too small, needs {} bytes",
+                    needed
+                )
+            }
+            SyscallError::DecodeFailed(err) => write!(f, "failed to decode syscall data: {}", err),
+            SyscallError::Unknown(dis) => write!(f, "unknown syscall error {}", dis),
+        }
+    }
+}
+
+impl<Err: ErrorCode + Debug> core::error::Error for SyscallError<Err> {}
+
 impl<Err: Copy> From<EncodeError> for SyscallError<Err> {
     fn from(value: EncodeError) -> Self {
         match value {
             EncodeError::AllocationError => Self::AllocationError,
-            EncodeError::PrimitiveError => Self::InvalidData,
+            EncodeError::PrimitiveError | EncodeError::UntranslatableAddress => Self::InvalidData,
         }
     }
 }
@@ -28,6 +254,9 @@ impl<Err: Copy> From<DecodeError> for SyscallError<Err> {
     fn from(value: DecodeError) -> Self {
         match value {
             DecodeError::InvalidData => Self::InvalidData,
+            DecodeError::BadDiscriminant | DecodeError::BadBool | DecodeError::SpillFault => {
+                Self::DecodeFailed(value)
+            }
             DecodeError::InvalidNum => Self::InvalidNum,
         }
     }
@@ -35,36 +264,70 @@ impl<Err: Copy> From<DecodeError> for SyscallError<Err> {
 
 impl<
         'a,
-        Err: SyscallEncodable<'a, Abi, EncodedType, Abi::RetEncoder<'a>>,
+        'buf,
+        Err: SyscallEncodable<'a, Abi, EncodedType, Abi::RetEncoder<'a, 'buf>>,
         Abi: SyscallAbi,
         EncodedType: Copy,
-    > SyscallEncodable<'a, Abi, EncodedType, Abi::RetEncoder<'a>> for SyscallError<Err>
+    > SyscallEncodable<'a, Abi, EncodedType, Abi::RetEncoder<'a, 'buf>> for SyscallError<Err>
 where
-    <Abi as SyscallAbi>::RetEncoder<'a>: SyscallEncoder<'a, Abi, EncodedType>,
+    <Abi as SyscallAbi>::RetEncoder<'a, 'buf>: SyscallEncoder<'a, Abi, EncodedType>,
 {
-    fn encode(&self, encoder: &mut Abi::RetEncoder<'a>) -> Result<(), EncodeError> {
+    fn encode(&self, encoder: &mut Abi::RetEncoder<'a, 'buf>) -> Result<(), EncodeError> {
         match *self {
-            SyscallError::InvalidData => encoder.encode(&0u8),
-            SyscallError::InvalidNum => encoder.encode(&1u8),
-            SyscallError::AllocationError => encoder.encode(&2u8),
+            SyscallError::InvalidData => encoder.encode(&DISC_INVALID_DATA),
+            SyscallError::InvalidNum => encoder.encode(&DISC_INVALID_NUM),
+            SyscallError::AllocationError => encoder.encode(&DISC_ALLOCATION_ERROR),
             SyscallError::SyscallError(e) => {
-                encoder.encode(&3u8)?;
+                encoder.encode(&DISC_SYSCALL_ERROR)?;
                 encoder.encode(&e)
             }
+            SyscallError::VersionTooOld => encoder.encode(&DISC_VERSION_TOO_OLD),
+            SyscallError::VersionTooNew => encoder.encode(&DISC_VERSION_TOO_NEW),
+            SyscallError::AbiMismatch => encoder.encode(&DISC_ABI_MISMATCH),
+            SyscallError::Interrupted => encoder.encode(&DISC_INTERRUPTED),
+            SyscallError::Restart(state) => {
+                encoder.encode(&DISC_RESTART)?;
+                encoder.encode(&state)
+            }
+            SyscallError::BufferTooSmall(needed) => {
+                encoder.encode(&DISC_BUFFER_TOO_SMALL)?;
+                encoder.encode(&(needed as u64))
+            }
+            SyscallError::DecodeFailed(err) => {
+                encoder.encode(&DISC_DECODE_FAILED)?;
+                encoder.encode(&decode_error_code(err))
+            }
+            SyscallError::Unknown(dis) => encoder.encode(&dis),
         }
     }
 
-    fn decode(decoder: &mut Abi::RetEncoder<'a>) -> Result<Self, DecodeError>
+    fn decode(decoder: &mut Abi::RetEncoder<'a, 'buf>) -> Result<Self, DecodeError>
     where
         Self: Sized,
     {
         let dis = decoder.decode_u8()?;
         Ok(match dis {
-            0 => SyscallError::InvalidData,
-            1 => SyscallError::InvalidNum,
-            2 => SyscallError::AllocationError,
-            3 => SyscallError::SyscallError(decoder.decode()?),
-            _ => SyscallError::InvalidData,
+            DISC_INVALID_DATA => SyscallError::InvalidData,
+            DISC_INVALID_NUM => SyscallError::InvalidNum,
+            DISC_ALLOCATION_ERROR => SyscallError::AllocationError,
+            DISC_SYSCALL_ERROR => SyscallError::SyscallError(decoder.decode()?),
+            DISC_VERSION_TOO_OLD => SyscallError::VersionTooOld,
+            DISC_VERSION_TOO_NEW => SyscallError::VersionTooNew,
+            DISC_ABI_MISMATCH => SyscallError::AbiMismatch,
+            DISC_INTERRUPTED => SyscallError::Interrupted,
+            DISC_RESTART => SyscallError::Restart(decoder.decode()?),
+            DISC_BUFFER_TOO_SMALL => {
+                let needed: u64 = decoder.decode()?;
+                SyscallError::BufferTooSmall(needed.try_into().unwrap_or(usize::MAX))
+            }
+            DISC_DECODE_FAILED => {
+                SyscallError::DecodeFailed(decode_error_from_code(decoder.decode_u8()?))
+            }
+            _ => SyscallError::Unknown(dis),
         })
     }
 }
+
+impl<Err: Copy + AbiHash> AbiHash for SyscallError<Err> {
+    const ABI_HASH: u64 = hash_u64(hash_str(HASH_SEED, "SyscallError"), Err::ABI_HASH);
+}
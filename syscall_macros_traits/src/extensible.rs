@@ -0,0 +1,145 @@
+use core::{marker::PhantomData, mem::MaybeUninit};
+
+use crate::{
+    abi::SyscallAbi,
+    api::SyscallEncodable,
+    encoder::{DecodeError, EncodeError, SyscallEncoder},
+    fingerprint::{hash_str, hash_u64, AbiHash, HASH_SEED},
+    ptr::{UserPointer, UserSlice},
+};
+
+/// Types for which every bit pattern is a valid value, so they may be copied out of user memory byte by byte.
+///
+/// # Safety
+/// Every bit pattern of size_of::<Self>() bytes, including all zeros, must be a valid value of Self.
+pub unsafe trait Plain: Copy {}
+
+macro_rules! impl_plain {
+    ($($t:ty),*) => {
+        $(unsafe impl Plain for $t {})*
+    };
+}
+
+impl_plain!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+unsafe impl<T: Plain, const N: usize> Plain for [T; N] {}
+
+/// A struct that may grow over time by adding fields to the end, in the style of Linux's openat2 and clone3.
+/// Callers pass a pointer to the struct and its size (see [UserExtensible]). When copying it in, the kernel
+/// sets the fields that the caller did not know about to their defaults, and rejects callers that set fields
+/// the kernel does not know about.
+///
+/// Derived by [crate::api::SyscallEncodable] for structs marked `#[syscall(extensible)]`. Fields added after
+/// the first version are marked `#[syscall(since = N)]`, optionally with `default = expr` (otherwise the
+/// field's [Default] is used), and must come after all fields of earlier versions. All fields must be [Plain],
+/// and the struct should not have implicit padding, since it is copied as is.
+///
+/// # Safety
+/// Self must be repr(C), every field of Self must be [Plain], and MIN_SIZE must be at most size_of::<Self>().
+pub unsafe trait ExtensibleStruct: Copy {
+    /// The size of the first version of this struct. Smaller structs are rejected.
+    const MIN_SIZE: usize;
+    /// The newest version of this struct.
+    const VERSION: u32;
+
+    /// Set every field that does not fit entirely in the first size bytes of the struct to its default.
+    fn fill_defaults(&mut self, size: usize);
+}
+
+/// Errors that occur when the kernel copies in a [UserExtensible].
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Ord, Eq, Hash)]
+pub enum ExtensibleError {
+    /// The pointer was null, or the verification closure rejected the memory.
+    InvalidPointer,
+    /// The caller's struct is smaller than the first version of the struct.
+    TooSmall,
+    /// The caller set fields that come after the end of the kernel's struct.
+    UnknownFields,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A pointer to an [ExtensibleStruct] in user memory, along with the size of the struct the caller was built
+/// with.
+pub struct UserExtensible<'abi, T> {
+    pub(crate) bytes: UserSlice<'abi, u8>,
+    _pd: PhantomData<&'abi T>,
+}
+
+impl<'abi, T> UserExtensible<'abi, T> {
+    /// Construct a new user extensible struct.
+    pub fn new(value: &'abi T) -> Self {
+        Self::from_parts(
+            UserPointer::from_raw((value as *const T).expose_addr()),
+            core::mem::size_of::<T>(),
+        )
+    }
+
+    /// Construct a new user extensible struct from a pointer and the size of the caller's struct.
+    pub fn from_parts(ptr: UserPointer<'abi, u8>, size: usize) -> Self {
+        Self {
+            bytes: UserSlice::from_parts(ptr, size),
+            _pd: PhantomData,
+        }
+    }
+
+    /// Get the size of the caller's struct.
+    pub fn size(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl<'abi, T: ExtensibleStruct> UserExtensible<'abi, T> {
+    /// Verify the caller's struct, and copy it in. Fields that the caller's struct is too small to contain
+    /// are set to their defaults, and any bytes past the end of T must be zero. The closure should verify the
+    /// memory as in [crate::ptr::UserPointer::as_ref].
+    pub fn copy_in(&self, f: impl FnOnce(*const u8, usize) -> bool) -> Result<T, ExtensibleError> {
+        let size = self.size();
+        if size < T::MIN_SIZE {
+            return Err(ExtensibleError::TooSmall);
+        }
+        let bytes = self
+            .bytes
+            .as_ref(f)
+            .ok_or(ExtensibleError::InvalidPointer)?;
+        let known = size.min(core::mem::size_of::<T>());
+        if bytes[known..].iter().any(|b| *b != 0) {
+            return Err(ExtensibleError::UnknownFields);
+        }
+        let mut value = MaybeUninit::<T>::zeroed();
+        // Safety: known is at most the size of both the source and the destination.
+        unsafe {
+            core::ptr::copy_nonoverlapping(bytes.as_ptr(), value.as_mut_ptr().cast::<u8>(), known)
+        };
+        // Safety: every field of T is Plain, so any bytes, including zeros, are valid.
+        let mut value = unsafe { value.assume_init() };
+        value.fill_defaults(size);
+        Ok(value)
+    }
+}
+
+impl<'a, Abi: SyscallAbi, EncodedType: Copy, Encoder, T: Copy>
+    SyscallEncodable<'a, Abi, EncodedType, Encoder> for UserExtensible<'a, T>
+where
+    Encoder: SyscallEncoder<'a, Abi, EncodedType>,
+{
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), EncodeError> {
+        self.bytes.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Encoder) -> Result<Self, DecodeError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            bytes: UserSlice::decode(decoder)?,
+            _pd: PhantomData,
+        })
+    }
+}
+
+impl<'abi, T> AbiHash for UserExtensible<'abi, T> {
+    const ABI_HASH: u64 = hash_u64(
+        hash_str(HASH_SEED, "UserExtensible"),
+        core::mem::size_of::<T>() as u64,
+    );
+}
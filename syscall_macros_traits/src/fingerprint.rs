@@ -0,0 +1,60 @@
+/// A compile-time hash of how a type is encoded, so that userspace and kernels built from different revisions
+/// of a syscall interface can detect that they disagree. Derived along with [crate::api::SyscallEncodable],
+/// from the names, types and order of fields, and the discriminants of enum variants. Types that implement
+/// SyscallEncodable by hand should implement this too, usually by hashing a name for the type along with the
+/// hashes of any types it encodes.
+pub trait AbiHash {
+    /// The hash of this type's encoding.
+    const ABI_HASH: u64;
+}
+
+/// The starting value for hashes built with [hash_str] and [hash_u64].
+pub const HASH_SEED: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Mix a string into a hash.
+pub const fn hash_str(hash: u64, s: &str) -> u64 {
+    let bytes = s.as_bytes();
+    let mut hash = hash;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash = (hash ^ bytes[i] as u64).wrapping_mul(FNV_PRIME);
+        i += 1;
+    }
+    // Terminate the string, so that adjacent strings can't run together.
+    (hash ^ 0xff).wrapping_mul(FNV_PRIME)
+}
+
+/// Mix an integer, such as another type's hash, into a hash.
+pub const fn hash_u64(hash: u64, value: u64) -> u64 {
+    let bytes = value.to_le_bytes();
+    let mut hash = hash;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash = (hash ^ bytes[i] as u64).wrapping_mul(FNV_PRIME);
+        i += 1;
+    }
+    hash
+}
+
+macro_rules! impl_abi_hash_prim {
+    ($($t:ty),*) => {
+        $(impl AbiHash for $t {
+            const ABI_HASH: u64 = hash_str(HASH_SEED, stringify!($t));
+        })*
+    };
+}
+
+impl_abi_hash_prim!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+impl_abi_hash_prim!(bool, ());
+
+impl<T: AbiHash> AbiHash for Option<T> {
+    const ABI_HASH: u64 = hash_u64(hash_str(HASH_SEED, "Option"), T::ABI_HASH);
+}
+
+impl<T: AbiHash, E: AbiHash> AbiHash for Result<T, E> {
+    const ABI_HASH: u64 = hash_u64(
+        hash_u64(hash_str(HASH_SEED, "Result"), T::ABI_HASH),
+        E::ABI_HASH,
+    );
+}
@@ -0,0 +1,318 @@
+/// Define a set of flags that is encoded as its underlying integer type. For example:
+///
+/// ```no_compile
+/// syscall_flags! {
+///     pub struct OpenFlags: u32 {
+///         const READ = 1;
+///         const WRITE = 2;
+///         const CREATE = 4;
+///     }
+/// }
+/// ```
+///
+/// By default, decoding a value that has any undefined bits set fails with [crate::encoder::DecodeError::InvalidData].
+/// A flag type that should tolerate bits defined by newer versions of the interface can instead keep them:
+///
+/// ```no_compile
+/// syscall_flags! {
+///     unknown_bits = retain;
+///     pub struct MapFlags: u64 {
+///         const SHARED = 1;
+///     }
+/// }
+/// ```
+///
+/// Retained unknown bits are reported by `unknown_bits()`, and are preserved when the value is re-encoded.
+#[macro_export]
+macro_rules! syscall_flags {
+    (
+        unknown_bits = $mode:ident;
+        $(#[$outer:meta])*
+        $vis:vis struct $name:ident: $ty:ty {
+            $(
+                $(#[$inner:meta])*
+                const $flag:ident = $value:expr;
+            )*
+        }
+    ) => {
+        $(#[$outer])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+        #[repr(transparent)]
+        $vis struct $name {
+            bits: $ty,
+        }
+
+        #[allow(dead_code)]
+        impl $name {
+            $(
+                $(#[$inner])*
+                pub const $flag: Self = Self { bits: $value };
+            )*
+
+            /// The union of all defined flags.
+            pub const ALL_BITS: $ty = 0 $(| $value)*;
+
+            /// The empty flag set.
+            pub const fn empty() -> Self {
+                Self { bits: 0 }
+            }
+
+            /// The set of all defined flags.
+            pub const fn all() -> Self {
+                Self {
+                    bits: Self::ALL_BITS,
+                }
+            }
+
+            /// Get the underlying bits.
+            pub const fn bits(&self) -> $ty {
+                self.bits
+            }
+
+            /// Construct from bits, returning None if any undefined bit is set.
+            pub const fn from_bits(bits: $ty) -> Option<Self> {
+                if bits & !Self::ALL_BITS != 0 {
+                    None
+                } else {
+                    Some(Self { bits })
+                }
+            }
+
+            /// Construct from bits, dropping any undefined bits.
+            pub const fn from_bits_truncate(bits: $ty) -> Self {
+                Self {
+                    bits: bits & Self::ALL_BITS,
+                }
+            }
+
+            /// Construct from bits, keeping any undefined bits.
+            pub const fn from_bits_retain(bits: $ty) -> Self {
+                Self { bits }
+            }
+
+            /// Any bits set in this value that are not defined flags.
+            pub const fn unknown_bits(&self) -> $ty {
+                self.bits & !Self::ALL_BITS
+            }
+
+            /// Are no flags set?
+            pub const fn is_empty(&self) -> bool {
+                self.bits == 0
+            }
+
+            /// Are all defined flags set?
+            pub const fn is_all(&self) -> bool {
+                self.bits & Self::ALL_BITS == Self::ALL_BITS
+            }
+
+            /// Are all the flags in other also set in self?
+            pub const fn contains(&self, other: Self) -> bool {
+                self.bits & other.bits == other.bits
+            }
+
+            /// Are any of the flags in other also set in self?
+            pub const fn intersects(&self, other: Self) -> bool {
+                self.bits & other.bits != 0
+            }
+
+            /// Set the flags in other.
+            pub fn insert(&mut self, other: Self) {
+                self.bits |= other.bits;
+            }
+
+            /// Clear the flags in other.
+            pub fn remove(&mut self, other: Self) {
+                self.bits &= !other.bits;
+            }
+
+            /// Flip the flags in other.
+            pub fn toggle(&mut self, other: Self) {
+                self.bits ^= other.bits;
+            }
+
+            /// Set or clear the flags in other.
+            pub fn set(&mut self, other: Self, value: bool) {
+                if value {
+                    self.insert(other)
+                } else {
+                    self.remove(other)
+                }
+            }
+        }
+
+        impl $crate::_core::ops::BitOr for $name {
+            type Output = Self;
+            fn bitor(self, rhs: Self) -> Self {
+                Self::from_bits_retain(self.bits | rhs.bits)
+            }
+        }
+
+        impl $crate::_core::ops::BitAnd for $name {
+            type Output = Self;
+            fn bitand(self, rhs: Self) -> Self {
+                Self::from_bits_retain(self.bits & rhs.bits)
+            }
+        }
+
+        impl $crate::_core::ops::BitXor for $name {
+            type Output = Self;
+            fn bitxor(self, rhs: Self) -> Self {
+                Self::from_bits_retain(self.bits ^ rhs.bits)
+            }
+        }
+
+        impl $crate::_core::ops::Sub for $name {
+            type Output = Self;
+            fn sub(self, rhs: Self) -> Self {
+                Self::from_bits_retain(self.bits & !rhs.bits)
+            }
+        }
+
+        impl $crate::_core::ops::Not for $name {
+            type Output = Self;
+            fn not(self) -> Self {
+                Self::from_bits_truncate(!self.bits)
+            }
+        }
+
+        impl $crate::_core::ops::BitOrAssign for $name {
+            fn bitor_assign(&mut self, rhs: Self) {
+                self.insert(rhs)
+            }
+        }
+
+        impl $crate::_core::ops::BitAndAssign for $name {
+            fn bitand_assign(&mut self, rhs: Self) {
+                self.bits &= rhs.bits;
+            }
+        }
+
+        impl $crate::_core::ops::BitXorAssign for $name {
+            fn bitxor_assign(&mut self, rhs: Self) {
+                self.toggle(rhs)
+            }
+        }
+
+        impl $crate::_core::ops::SubAssign for $name {
+            fn sub_assign(&mut self, rhs: Self) {
+                self.remove(rhs)
+            }
+        }
+
+        $crate::__syscall_flags_encodable!($mode, $name, $ty);
+    };
+    (
+        $(#[$outer:meta])*
+        $vis:vis struct $name:ident: $ty:ty {
+            $(
+                $(#[$inner:meta])*
+                const $flag:ident = $value:expr;
+            )*
+        }
+    ) => {
+        $crate::syscall_flags! {
+            unknown_bits = reject;
+            $(#[$outer])*
+            $vis struct $name: $ty {
+                $(
+                    $(#[$inner])*
+                    const $flag = $value;
+                )*
+            }
+        }
+    };
+}
+
+/// Implement SyscallEncodable for a type generated by the [bitflags](https://docs.rs/bitflags) crate. The
+/// type is encoded as its bits, and undefined bits are rejected on decode unless `retain` is given.
+///
+/// ```no_compile
+/// bitflags::bitflags! {
+///     #[derive(Clone, Copy)]
+///     pub struct Prot: u32 {
+///         const READ = 1;
+///         const WRITE = 2;
+///     }
+/// }
+/// bitflags_encodable!(Prot);
+/// ```
+#[cfg(feature = "bitflags")]
+#[macro_export]
+macro_rules! bitflags_encodable {
+    (retain $name:ty) => {
+        $crate::__syscall_flags_encodable!(
+            retain,
+            $name,
+            <$name as $crate::_bitflags::Flags>::Bits
+        );
+    };
+    ($name:ty) => {
+        $crate::__syscall_flags_encodable!(
+            reject,
+            $name,
+            <$name as $crate::_bitflags::Flags>::Bits
+        );
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __syscall_flags_encodable {
+    ($mode:ident, $name:ty, $ty:ty) => {
+        impl<'a, Abi: $crate::abi::SyscallAbi, EncodedType: Copy, Encoder>
+            $crate::api::SyscallEncodable<'a, Abi, EncodedType, Encoder> for $name
+        where
+            Encoder: $crate::encoder::SyscallEncoder<'a, Abi, EncodedType>,
+        {
+            fn encode(&self, encoder: &mut Encoder) -> Result<(), $crate::encoder::EncodeError> {
+                <$ty as $crate::api::SyscallEncodable<'a, Abi, EncodedType, Encoder>>::encode(
+                    &self.bits(),
+                    encoder,
+                )
+            }
+
+            fn decode(decoder: &mut Encoder) -> Result<Self, $crate::encoder::DecodeError>
+            where
+                Self: Sized,
+            {
+                let bits =
+                    <$ty as $crate::api::SyscallEncodable<'a, Abi, EncodedType, Encoder>>::decode(
+                        decoder,
+                    )?;
+                $crate::__syscall_flags_encodable!(@decode $mode, bits)
+            }
+        }
+
+        impl $crate::fingerprint::AbiHash for $name {
+            const ABI_HASH: u64 = $crate::fingerprint::hash_u64(
+                $crate::fingerprint::hash_str($crate::fingerprint::HASH_SEED, "flags"),
+                <$ty as $crate::fingerprint::AbiHash>::ABI_HASH,
+            );
+        }
+
+        // Safety: flags are a transparent wrapper around an integer. Undefined bits are checked as on decode.
+        unsafe impl $crate::deep_copy::UserCopy for $name {
+            fn copy_refs<F: FnMut(*const u8, usize) -> bool>(
+                &mut self,
+                _ctx: &mut $crate::deep_copy::DeepCopy<'_, F>,
+            ) -> Result<(), $crate::deep_copy::DeepCopyError> {
+                $crate::__syscall_flags_encodable!(@validate $mode, self)
+            }
+        }
+    };
+    (@validate reject, $self:ident) => {
+        match Self::from_bits($self.bits()) {
+            Some(_) => Ok(()),
+            None => Err($crate::deep_copy::DeepCopyError::InvalidData),
+        }
+    };
+    (@validate retain, $self:ident) => {
+        Ok(())
+    };
+    (@decode reject, $bits:ident) => {
+        Self::from_bits($bits).ok_or($crate::encoder::DecodeError::InvalidData)
+    };
+    (@decode retain, $bits:ident) => {
+        Ok(Self::from_bits_retain($bits))
+    };
+}
@@ -0,0 +1,116 @@
+use core::{fmt::Debug, hash::Hash, marker::PhantomData};
+
+use crate::{
+    abi::SyscallAbi,
+    api::SyscallEncodable,
+    deep_copy::{DeepCopy, DeepCopyError, UserCopy},
+    encoder::{DecodeError, EncodeError, SyscallEncoder},
+    fingerprint::{hash_str, hash_u64, AbiHash, HASH_SEED},
+};
+
+#[cfg(feature = "handle-table")]
+mod table;
+#[cfg(feature = "handle-table")]
+pub use table::{HandleError, HandleTable};
+
+/// A handle to a kernel object of type Kind, as an index and a generation packed into a u64. Kind is only a
+/// marker, so a handle for one kind of object cannot be passed where another kind is expected.
+pub struct Handle<Kind> {
+    raw: u64,
+    _pd: PhantomData<fn() -> Kind>,
+}
+
+impl<Kind> Handle<Kind> {
+    /// Construct a handle from an index and a generation.
+    pub const fn new(index: u32, generation: u32) -> Self {
+        Self::from_raw(((generation as u64) << 32) | index as u64)
+    }
+
+    /// Construct a handle from its raw representation.
+    pub const fn from_raw(raw: u64) -> Self {
+        Self {
+            raw,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Get the raw representation of this handle.
+    pub const fn into_raw(self) -> u64 {
+        self.raw
+    }
+
+    /// Get the table index of this handle.
+    pub const fn index(&self) -> u32 {
+        self.raw as u32
+    }
+
+    /// Get the generation of this handle.
+    pub const fn generation(&self) -> u32 {
+        (self.raw >> 32) as u32
+    }
+}
+
+impl<Kind> Clone for Handle<Kind> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Kind> Copy for Handle<Kind> {}
+
+impl<Kind> PartialEq for Handle<Kind> {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw == other.raw
+    }
+}
+
+impl<Kind> Eq for Handle<Kind> {}
+
+impl<Kind> Hash for Handle<Kind> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.raw.hash(state)
+    }
+}
+
+impl<Kind> Debug for Handle<Kind> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Handle")
+            .field("kind", &core::any::type_name::<Kind>())
+            .field("index", &self.index())
+            .field("generation", &self.generation())
+            .finish()
+    }
+}
+
+impl<'a, Abi: SyscallAbi, EncodedType: Copy, Encoder, Kind>
+    SyscallEncodable<'a, Abi, EncodedType, Encoder> for Handle<Kind>
+where
+    Encoder: SyscallEncoder<'a, Abi, EncodedType>,
+{
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), EncodeError> {
+        self.raw.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Encoder) -> Result<Self, DecodeError>
+    where
+        Self: Sized,
+    {
+        Ok(Self::from_raw(u64::decode(decoder)?))
+    }
+}
+
+// Handles for different kinds of object must not be confused, so the kind is part of the hash. Marker types
+// usually implement AbiHash by hashing their name with [hash_str].
+impl<Kind: AbiHash> AbiHash for Handle<Kind> {
+    const ABI_HASH: u64 = hash_u64(hash_str(HASH_SEED, "Handle"), Kind::ABI_HASH);
+}
+
+// Safety: a handle is a u64.
+unsafe impl<Kind> UserCopy for Handle<Kind> {
+    fn copy_refs<F: FnMut(*const u8, usize) -> bool>(
+        &mut self,
+        _ctx: &mut DeepCopy<'_, F>,
+    ) -> Result<(), DeepCopyError> {
+        Ok(())
+    }
+}
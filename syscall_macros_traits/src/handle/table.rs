@@ -0,0 +1,112 @@
+use core::marker::PhantomData;
+
+use super::Handle;
+
+/// Errors that occur when looking up a handle in a [HandleTable].
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Ord, Eq, Hash)]
+pub enum HandleError {
+    /// The handle's index is outside the table.
+    Invalid,
+    /// The handle refers to a slot that was revoked, and possibly reused.
+    Stale,
+}
+
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+/// A kernel-side table of up to N objects of type T, referred to by handles of type [Handle<Kind>]. Revoking
+/// a handle bumps the slot's generation, so old copies of the handle are detected as stale.
+pub struct HandleTable<Kind, T, const N: usize> {
+    slots: [Slot<T>; N],
+    count: usize,
+    _pd: PhantomData<fn() -> Kind>,
+}
+
+impl<Kind, T, const N: usize> HandleTable<Kind, T, N> {
+    /// Create a new, empty table.
+    pub fn new() -> Self {
+        Self {
+            // Generations start at 1, so a zero handle is never valid.
+            slots: core::array::from_fn(|_| Slot {
+                generation: 1,
+                value: None,
+            }),
+            count: 0,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Get the number of objects in the table.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Is the table empty?
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Insert an object, returning a new handle to it. If the table is full, the object is returned.
+    pub fn insert(&mut self, value: T) -> Result<Handle<Kind>, T> {
+        let Some((index, slot)) = self
+            .slots
+            .iter_mut()
+            .enumerate()
+            .find(|(_, slot)| slot.value.is_none())
+        else {
+            return Err(value);
+        };
+        slot.value = Some(value);
+        self.count += 1;
+        Ok(Handle::new(index as u32, slot.generation))
+    }
+
+    fn slot(&self, handle: Handle<Kind>) -> Result<&Slot<T>, HandleError> {
+        let slot = self
+            .slots
+            .get(handle.index() as usize)
+            .ok_or(HandleError::Invalid)?;
+        if slot.generation != handle.generation() || slot.value.is_none() {
+            return Err(HandleError::Stale);
+        }
+        Ok(slot)
+    }
+
+    fn slot_mut(&mut self, handle: Handle<Kind>) -> Result<&mut Slot<T>, HandleError> {
+        let slot = self
+            .slots
+            .get_mut(handle.index() as usize)
+            .ok_or(HandleError::Invalid)?;
+        if slot.generation != handle.generation() || slot.value.is_none() {
+            return Err(HandleError::Stale);
+        }
+        Ok(slot)
+    }
+
+    /// Look up the object referred to by a handle.
+    pub fn get(&self, handle: Handle<Kind>) -> Result<&T, HandleError> {
+        Ok(self.slot(handle)?.value.as_ref().unwrap())
+    }
+
+    /// Look up the object referred to by a handle, mutably.
+    pub fn get_mut(&mut self, handle: Handle<Kind>) -> Result<&mut T, HandleError> {
+        Ok(self.slot_mut(handle)?.value.as_mut().unwrap())
+    }
+
+    /// Remove the object referred to by a handle, and invalidate the handle.
+    pub fn revoke(&mut self, handle: Handle<Kind>) -> Result<T, HandleError> {
+        let slot = self.slot_mut(handle)?;
+        slot.generation = slot.generation.checked_add(1).unwrap_or(1);
+        let value = slot.value.take().unwrap();
+        self.count -= 1;
+        Ok(value)
+    }
+}
+
+impl<Kind, T, const N: usize> Default for HandleTable<Kind, T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
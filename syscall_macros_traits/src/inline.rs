@@ -0,0 +1,290 @@
+use crate::{
+    abi::SyscallAbi,
+    api::SyscallEncodable,
+    encoder::{DecodeError, EncodeError, SyscallEncoder},
+    fingerprint::{hash_str, hash_u64, AbiHash, HASH_SEED},
+    ptr::UserSlice,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Up to N bytes that are encoded by value, rather than behind a pointer.
+pub struct InlineBytes<const N: usize> {
+    pub(crate) len: usize,
+    data: [u8; N],
+}
+
+impl<const N: usize> InlineBytes<N> {
+    /// Construct a new inline byte buffer. Returns None if bytes is longer than N.
+    pub fn new(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() > N {
+            return None;
+        }
+        let mut data = [0; N];
+        data[..bytes.len()].copy_from_slice(bytes);
+        Some(Self {
+            len: bytes.len(),
+            data,
+        })
+    }
+
+    /// Get the bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// Get the number of bytes stored.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Is this buffer empty?
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The maximum number of bytes this buffer can hold.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> Default for InlineBytes<N> {
+    fn default() -> Self {
+        Self {
+            len: 0,
+            data: [0; N],
+        }
+    }
+}
+
+impl<'b, const N: usize> TryFrom<&'b [u8]> for InlineBytes<N> {
+    type Error = EncodeError;
+
+    fn try_from(value: &'b [u8]) -> Result<Self, Self::Error> {
+        Self::new(value).ok_or(EncodeError::PrimitiveError)
+    }
+}
+
+impl<'a, Abi: SyscallAbi, EncodedType: Copy, Encoder, const N: usize>
+    SyscallEncodable<'a, Abi, EncodedType, Encoder> for InlineBytes<N>
+where
+    Encoder: SyscallEncoder<'a, Abi, EncodedType>,
+{
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), EncodeError> {
+        self.len.encode(encoder)?;
+        for b in self.as_bytes() {
+            encoder.encode_u8(*b)?;
+        }
+        Ok(())
+    }
+
+    fn decode(decoder: &mut Encoder) -> Result<Self, DecodeError>
+    where
+        Self: Sized,
+    {
+        let len = usize::decode(decoder)?;
+        if len > N {
+            return Err(DecodeError::InvalidData);
+        }
+        let mut data = [0; N];
+        for b in &mut data[..len] {
+            *b = decoder.decode_u8()?;
+        }
+        Ok(Self { len, data })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+/// A string of up to N bytes that is encoded by value. Encodes the same way as [InlineBytes], and
+/// is validated as UTF-8 on decode.
+pub struct InlineStr<const N: usize> {
+    pub(crate) bytes: InlineBytes<N>,
+}
+
+impl<const N: usize> InlineStr<N> {
+    /// Construct a new inline string. Returns None if s is longer than N bytes.
+    pub fn new(s: &str) -> Option<Self> {
+        Some(Self {
+            bytes: InlineBytes::new(s.as_bytes())?,
+        })
+    }
+
+    /// Get the string.
+    pub fn as_str(&self) -> &str {
+        // Safety: bytes is only ever constructed from a str, or validated as UTF-8 during decode or deep copy.
+        unsafe { core::str::from_utf8_unchecked(self.bytes.as_bytes()) }
+    }
+
+    /// Get the length of the string, in bytes.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Is this string empty?
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+impl<'b, const N: usize> TryFrom<&'b str> for InlineStr<N> {
+    type Error = EncodeError;
+
+    fn try_from(value: &'b str) -> Result<Self, Self::Error> {
+        Self::new(value).ok_or(EncodeError::PrimitiveError)
+    }
+}
+
+impl<'a, Abi: SyscallAbi, EncodedType: Copy, Encoder, const N: usize>
+    SyscallEncodable<'a, Abi, EncodedType, Encoder> for InlineStr<N>
+where
+    Encoder: SyscallEncoder<'a, Abi, EncodedType>,
+{
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), EncodeError> {
+        self.bytes.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Encoder) -> Result<Self, DecodeError>
+    where
+        Self: Sized,
+    {
+        let bytes = InlineBytes::<N>::decode(decoder)?;
+        core::str::from_utf8(bytes.as_bytes()).map_err(|_| DecodeError::InvalidData)?;
+        Ok(Self { bytes })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A slice that is encoded by value if it has at most N elements, and otherwise falls back to a [UserSlice].
+pub struct SmallSlice<'abi, T, const N: usize> {
+    repr: SmallSliceRepr<'abi, T, N>,
+}
+
+// Kept private, so that an inline slice's length is always at most N.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum SmallSliceRepr<'abi, T, const N: usize> {
+    /// The elements are stored inline.
+    Inline { len: usize, data: [T; N] },
+    /// The elements are left in user memory.
+    User(UserSlice<'abi, T>),
+}
+
+impl<'abi, T: Copy + Default, const N: usize> SmallSlice<'abi, T, N> {
+    /// Construct a new small slice, copying the elements inline if they fit.
+    pub fn new(slice: &'abi [T]) -> Self {
+        if slice.len() > N {
+            return Self {
+                repr: SmallSliceRepr::User(UserSlice::new(slice)),
+            };
+        }
+        let mut data = [T::default(); N];
+        data[..slice.len()].copy_from_slice(slice);
+        Self {
+            repr: SmallSliceRepr::Inline {
+                len: slice.len(),
+                data,
+            },
+        }
+    }
+}
+
+impl<'abi, T, const N: usize> SmallSlice<'abi, T, N> {
+    /// Are the elements stored inline?
+    pub fn is_inline(&self) -> bool {
+        matches!(self.repr, SmallSliceRepr::Inline { .. })
+    }
+
+    /// Get the length of the slice.
+    pub fn len(&self) -> usize {
+        match &self.repr {
+            SmallSliceRepr::Inline { len, .. } => *len,
+            SmallSliceRepr::User(slice) => slice.len(),
+        }
+    }
+
+    /// Is this slice empty?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'abi, T: Sync, const N: usize> SmallSlice<'abi, T, N> {
+    /// Get the elements. If the elements are stored in user memory, the closure is used to verify
+    /// the region, see [crate::ptr::UserPointer::as_ref].
+    pub fn as_ref<'s>(&'s self, f: impl FnOnce(*const T, usize) -> bool) -> Option<&'s [T]>
+    where
+        'abi: 's,
+    {
+        match &self.repr {
+            SmallSliceRepr::Inline { len, data } => Some(&data[..*len]),
+            SmallSliceRepr::User(slice) => slice.as_ref(f),
+        }
+    }
+}
+
+impl<'abi, T: Copy + Default, const N: usize> From<&'abi [T]> for SmallSlice<'abi, T, N> {
+    fn from(value: &'abi [T]) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<'a, Abi: SyscallAbi, EncodedType: Copy, Encoder, T, const N: usize>
+    SyscallEncodable<'a, Abi, EncodedType, Encoder> for SmallSlice<'a, T, N>
+where
+    Encoder: SyscallEncoder<'a, Abi, EncodedType>,
+    T: SyscallEncodable<'a, Abi, EncodedType, Encoder> + Default,
+{
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), EncodeError> {
+        match &self.repr {
+            SmallSliceRepr::Inline { len, data } => {
+                encoder.encode_u8(0)?;
+                len.encode(encoder)?;
+                for item in &data[..*len] {
+                    item.encode(encoder)?;
+                }
+                Ok(())
+            }
+            SmallSliceRepr::User(slice) => {
+                encoder.encode_u8(1)?;
+                slice.encode(encoder)
+            }
+        }
+    }
+
+    fn decode(decoder: &mut Encoder) -> Result<Self, DecodeError>
+    where
+        Self: Sized,
+    {
+        let dis = decoder.decode_u8()?;
+        let repr = match dis {
+            0 => {
+                let len = usize::decode(decoder)?;
+                if len > N {
+                    return Err(DecodeError::InvalidData);
+                }
+                let mut data = [T::default(); N];
+                for item in &mut data[..len] {
+                    *item = T::decode(decoder)?;
+                }
+                SmallSliceRepr::Inline { len, data }
+            }
+            1 => SmallSliceRepr::User(UserSlice::decode(decoder)?),
+            _ => return Err(DecodeError::BadDiscriminant),
+        };
+        Ok(Self { repr })
+    }
+}
+
+impl<const N: usize> AbiHash for InlineBytes<N> {
+    const ABI_HASH: u64 = hash_u64(hash_str(HASH_SEED, "InlineBytes"), N as u64);
+}
+
+impl<const N: usize> AbiHash for InlineStr<N> {
+    const ABI_HASH: u64 = hash_u64(hash_str(HASH_SEED, "InlineStr"), N as u64);
+}
+
+impl<'abi, T: AbiHash, const N: usize> AbiHash for SmallSlice<'abi, T, N> {
+    const ABI_HASH: u64 = hash_u64(
+        hash_u64(hash_str(HASH_SEED, "SmallSlice"), T::ABI_HASH),
+        N as u64,
+    );
+}
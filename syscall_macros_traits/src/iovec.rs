@@ -0,0 +1,249 @@
+use core::marker::PhantomData;
+
+use crate::{
+    abi::SyscallAbi,
+    api::SyscallEncodable,
+    encoder::SyscallEncoder,
+    fingerprint::{hash_str, AbiHash, HASH_SEED},
+    ptr::UserSlice,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[repr(C)]
+/// A single segment of a [UserIoVec], laid out as a base address and a length.
+pub struct IoVec<'abi> {
+    pub(crate) base: usize,
+    pub(crate) len: usize,
+    _pd: PhantomData<&'abi [u8]>,
+}
+
+impl<'abi> IoVec<'abi> {
+    /// Construct a new segment that the kernel may read.
+    pub fn new(buf: &'abi [u8]) -> Self {
+        Self {
+            base: buf.as_ptr().expose_addr(),
+            len: buf.len(),
+            _pd: PhantomData,
+        }
+    }
+
+    /// Construct a new segment that the kernel may read or write.
+    pub fn new_mut(buf: &'abi mut [u8]) -> Self {
+        Self {
+            base: buf.as_mut_ptr().expose_addr(),
+            len: buf.len(),
+            _pd: PhantomData,
+        }
+    }
+
+    /// Get the length of the segment.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Is this segment empty?
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<'abi> From<&'abi [u8]> for IoVec<'abi> {
+    fn from(value: &'abi [u8]) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<'abi> From<&'abi mut [u8]> for IoVec<'abi> {
+    fn from(value: &'abi mut [u8]) -> Self {
+        Self::new_mut(value)
+    }
+}
+
+/// Errors that occur when the kernel accesses a [UserIoVec].
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Ord, Eq, Hash)]
+pub enum IoVecError {
+    /// A pointer was null, or the verification closure rejected the memory.
+    InvalidPointer,
+    /// There are more segments than the kernel allows.
+    TooManySegments,
+    /// The total length of the segments overflows.
+    Overflow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A type representing a scatter-gather list of user buffers, as used by readv and writev style calls.
+pub struct UserIoVec<'abi> {
+    pub(crate) iovs: UserSlice<'abi, IoVec<'abi>>,
+}
+
+impl<'abi> UserIoVec<'abi> {
+    /// Construct a new user iovec from an array of segments.
+    pub fn new(iovs: &'abi [IoVec<'abi>]) -> Self {
+        Self {
+            iovs: UserSlice::new(iovs),
+        }
+    }
+
+    /// Construct a new user iovec from a list of buffers that the kernel may read. The segments are written
+    /// into storage, and None is returned if storage is too small.
+    pub fn from_slices(bufs: &[&'abi [u8]], storage: &'abi mut [IoVec<'abi>]) -> Option<Self> {
+        if bufs.len() > storage.len() {
+            return None;
+        }
+        for (iov, buf) in storage.iter_mut().zip(bufs) {
+            *iov = IoVec::new(buf);
+        }
+        let storage: &'abi [IoVec<'abi>] = storage;
+        Some(Self::new(&storage[..bufs.len()]))
+    }
+
+    /// Construct a new user iovec from a list of buffers that the kernel may read or write. See [Self::from_slices].
+    pub fn from_mut_slices(
+        bufs: &'abi mut [&'abi mut [u8]],
+        storage: &'abi mut [IoVec<'abi>],
+    ) -> Option<Self> {
+        if bufs.len() > storage.len() {
+            return None;
+        }
+        let count = bufs.len();
+        for (iov, buf) in storage.iter_mut().zip(bufs) {
+            *iov = IoVec::new_mut(buf);
+        }
+        let storage: &'abi [IoVec<'abi>] = storage;
+        Some(Self::new(&storage[..count]))
+    }
+
+    /// Get the number of segments.
+    pub fn len(&self) -> usize {
+        self.iovs.len()
+    }
+
+    /// Are there no segments?
+    pub fn is_empty(&self) -> bool {
+        self.iovs.is_empty()
+    }
+
+    /// Verify the segment array, copy it into storage, and return a cursor for copying data in and out of the
+    /// segments. At most storage.len() segments are allowed, and the total length of the segments must not exceed
+    /// isize::MAX. The cursor only uses the copy in storage, so the user changing the segment array afterwards
+    /// doesn't affect the lengths that were checked.
+    ///
+    /// The closure is called for the segment array, and then for each region of a segment before the cursor
+    /// accesses it. It should verify the memory as in [crate::ptr::UserPointer::as_ref], for the kind of access
+    /// the kernel intends to perform (reads for [IoVecCursor::copy_in], writes for [IoVecCursor::copy_out]).
+    pub fn cursor<'k, F: FnMut(*mut u8, usize) -> bool>(
+        &self,
+        storage: &'k mut [IoVec<'abi>],
+        mut f: F,
+    ) -> Result<IoVecCursor<'k, 'abi, F>, IoVecError> {
+        if self.len() > storage.len() {
+            return Err(IoVecError::TooManySegments);
+        }
+        let user_iovs = self
+            .iovs
+            .as_ref(|ptr, len| f(ptr as *mut u8, len))
+            .ok_or(IoVecError::InvalidPointer)?;
+        let iovs = &mut storage[..user_iovs.len()];
+        iovs.copy_from_slice(user_iovs);
+        let mut total = 0usize;
+        for iov in iovs.iter() {
+            total = total.checked_add(iov.len).ok_or(IoVecError::Overflow)?;
+        }
+        if total > isize::MAX as usize {
+            return Err(IoVecError::Overflow);
+        }
+        Ok(IoVecCursor {
+            iovs,
+            total,
+            seg: 0,
+            off: 0,
+            f,
+        })
+    }
+}
+
+/// A kernel-side cursor over the segments of a [UserIoVec], holding a kernel-side copy of the segment array.
+/// Copies advance through the segments in order.
+pub struct IoVecCursor<'k, 'abi, F> {
+    iovs: &'k [IoVec<'abi>],
+    total: usize,
+    seg: usize,
+    off: usize,
+    f: F,
+}
+
+impl<'k, 'abi, F: FnMut(*mut u8, usize) -> bool> IoVecCursor<'k, 'abi, F> {
+    /// The total length of all segments, as verified when the cursor was created.
+    pub fn total_len(&self) -> usize {
+        self.total
+    }
+
+    /// Verify and visit up to len bytes of user memory, calling op with the user pointer, the offset into the
+    /// transfer, and the number of bytes for each region.
+    fn transfer(
+        &mut self,
+        len: usize,
+        mut op: impl FnMut(*mut u8, usize, usize),
+    ) -> Result<usize, IoVecError> {
+        let mut done = 0;
+        while done < len && self.seg < self.iovs.len() {
+            let iov = self.iovs[self.seg];
+            if self.off >= iov.len {
+                self.seg += 1;
+                self.off = 0;
+                continue;
+            }
+            let count = (iov.len - self.off).min(len - done);
+            let addr = iov.base.checked_add(self.off).ok_or(IoVecError::Overflow)?;
+            let ptr: *mut u8 = core::ptr::from_exposed_addr_mut(addr);
+            if ptr.is_null() || !(self.f)(ptr, count) {
+                return Err(IoVecError::InvalidPointer);
+            }
+            op(ptr, done, count);
+            done += count;
+            self.off += count;
+        }
+        Ok(done)
+    }
+
+    /// Copy data from the segments into buf. Returns the number of bytes copied, which is less than buf.len()
+    /// only if the end of the segments was reached.
+    pub fn copy_in(&mut self, buf: &mut [u8]) -> Result<usize, IoVecError> {
+        let dst = buf.as_mut_ptr();
+        self.transfer(buf.len(), |ptr, off, count| {
+            // Safety: the source region was verified, and the destination is within buf.
+            unsafe { core::ptr::copy_nonoverlapping(ptr, dst.add(off), count) }
+        })
+    }
+
+    /// Copy data into the segments from data. Returns the number of bytes copied, which is less than data.len()
+    /// only if the end of the segments was reached.
+    pub fn copy_out(&mut self, data: &[u8]) -> Result<usize, IoVecError> {
+        let src = data.as_ptr();
+        self.transfer(data.len(), |ptr, off, count| {
+            // Safety: the destination region was verified, and the source is within data.
+            unsafe { core::ptr::copy_nonoverlapping(src.add(off), ptr, count) }
+        })
+    }
+}
+
+impl<'a, Abi: SyscallAbi, EncodedType: Copy, Encoder: SyscallEncoder<'a, Abi, EncodedType>>
+    SyscallEncodable<'a, Abi, EncodedType, Encoder> for UserIoVec<'a>
+{
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), crate::encoder::EncodeError> {
+        self.iovs.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Encoder) -> Result<Self, crate::encoder::DecodeError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            iovs: UserSlice::<'a, IoVec<'a>>::decode(decoder)?,
+        })
+    }
+}
+
+impl<'abi> AbiHash for UserIoVec<'abi> {
+    const ABI_HASH: u64 = hash_str(HASH_SEED, "UserIoVec");
+}
@@ -1,12 +1,29 @@
 #![feature(strict_provenance)]
 #![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 #[doc(hidden)]
 pub extern crate core as _core;
 
+#[cfg(feature = "bitflags")]
+#[doc(hidden)]
+pub extern crate bitflags as _bitflags;
+
 pub mod abi;
 pub mod api;
+pub mod deep_copy;
 pub mod encoder;
 pub mod error;
+pub mod extensible;
+pub mod fingerprint;
+pub mod flags;
+pub mod handle;
+pub mod inline;
+pub mod iovec;
+#[cfg(feature = "alloc")]
+pub mod owned;
 pub mod ptr;
+pub mod spill;
 pub mod table;
@@ -0,0 +1,249 @@
+//! Heap-backed arguments. Userspace sends a [Vec], [Box] or [String] as a user reference to its contents (e.g.
+//! `UserSlice::from(&vec)`), and the kernel copies the contents into an owned value of its own, up to a maximum
+//! length, so that a caller cannot make the kernel allocate without bound.
+//!
+//! Syscalls can instead put the limit in their arguments' types with [OwnedVec] and [OwnedString], which are
+//! sent the same way, but fail to decode if they are longer than their limit.
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::alloc::Layout;
+
+use crate::{
+    abi::SyscallAbi,
+    api::SyscallEncodable,
+    encoder::{DecodeError, EncodeError, SyscallEncoder},
+    fingerprint::{hash_str, hash_u64, AbiHash, HASH_SEED},
+    ptr::{UserPointer, UserSlice, UserStr},
+};
+
+/// Errors that occur when the kernel copies user memory into an owned value.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Ord, Eq, Hash)]
+pub enum OwnedCopyError {
+    /// The pointer was null, or the verification closure rejected the memory.
+    InvalidPointer,
+    /// The value is longer than the maximum length.
+    TooLong,
+    /// The string is not valid UTF-8.
+    InvalidUtf8,
+    /// The kernel could not allocate memory for the copy.
+    OutOfMemory,
+}
+
+impl<'abi, T> From<&'abi Box<T>> for UserPointer<'abi, T> {
+    fn from(value: &'abi Box<T>) -> Self {
+        UserPointer::new(value)
+    }
+}
+
+impl<'abi, T> From<&'abi Vec<T>> for UserSlice<'abi, T> {
+    fn from(value: &'abi Vec<T>) -> Self {
+        UserSlice::new(value)
+    }
+}
+
+impl<'abi, T> From<&'abi Box<[T]>> for UserSlice<'abi, T> {
+    fn from(value: &'abi Box<[T]>) -> Self {
+        UserSlice::new(value)
+    }
+}
+
+impl<'abi> From<&'abi String> for UserStr<'abi> {
+    fn from(value: &'abi String) -> Self {
+        UserStr::new(value)
+    }
+}
+
+impl<'abi> From<&'abi Box<str>> for UserStr<'abi> {
+    fn from(value: &'abi Box<str>) -> Self {
+        UserStr::new(value)
+    }
+}
+
+// Copy src into a new Vec, failing rather than aborting if the allocation fails.
+fn try_to_vec<T: Copy>(src: &[T]) -> Result<Vec<T>, OwnedCopyError> {
+    let mut v = Vec::new();
+    v.try_reserve_exact(src.len())
+        .map_err(|_| OwnedCopyError::OutOfMemory)?;
+    v.extend_from_slice(src);
+    Ok(v)
+}
+
+impl<'abi, T: Copy + Sync> UserPointer<'abi, T> {
+    /// Verify the pointer, and copy the value into a new Box. See [UserPointer::as_ref] for the verification
+    /// closure.
+    pub fn to_box(
+        &self,
+        f: impl FnOnce(*const T, usize) -> bool,
+    ) -> Result<Box<T>, OwnedCopyError> {
+        let value = self.as_ref(f).ok_or(OwnedCopyError::InvalidPointer)?;
+        let layout = Layout::new::<T>();
+        if layout.size() == 0 {
+            // Zero-sized boxes don't allocate.
+            return Ok(Box::new(*value));
+        }
+        // Safety: the layout has a non-zero size.
+        let ptr = unsafe { alloc::alloc::alloc(layout) } as *mut T;
+        if ptr.is_null() {
+            return Err(OwnedCopyError::OutOfMemory);
+        }
+        // Safety: ptr was allocated by the global allocator with T's layout, and is initialized before the Box
+        // takes ownership of it.
+        unsafe {
+            ptr.write(*value);
+            Ok(Box::from_raw(ptr))
+        }
+    }
+}
+
+impl<'abi, T: Copy + Sync> UserSlice<'abi, T> {
+    /// Verify the slice, and copy it into a new Vec, if it has no more than max_len elements. See
+    /// [UserPointer::as_ref] for the verification closure. Only the elements are copied, so any user references
+    /// within them still point into user memory (see [crate::deep_copy] for copying those).
+    pub fn to_vec(
+        &self,
+        max_len: usize,
+        f: impl FnOnce(*const T, usize) -> bool,
+    ) -> Result<Vec<T>, OwnedCopyError> {
+        if self.len() > max_len {
+            return Err(OwnedCopyError::TooLong);
+        }
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        try_to_vec(self.as_ref(f).ok_or(OwnedCopyError::InvalidPointer)?)
+    }
+}
+
+impl<'abi> UserStr<'abi> {
+    /// Verify the string, and copy it into a new String, if it is no more than max_len bytes long. Like
+    /// [UserStr::copy_into], the copy is validated as UTF-8 after copying. See [UserPointer::as_ref] for the
+    /// verification closure.
+    pub fn to_string(
+        &self,
+        max_len: usize,
+        f: impl FnOnce(*const u8, usize) -> bool,
+    ) -> Result<String, OwnedCopyError> {
+        let bytes = self.bytes.to_vec(max_len, f)?;
+        String::from_utf8(bytes).map_err(|_| OwnedCopyError::InvalidUtf8)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A slice of user memory with at most MAX elements, which the kernel copies into a [Vec]. Sent like a
+/// [UserSlice], but fails to decode with [DecodeError::InvalidData] if it is too long, so the limit is part of
+/// the syscall's definition rather than left to each handler.
+pub struct OwnedVec<'abi, T, const MAX: usize> {
+    slice: UserSlice<'abi, T>,
+}
+
+impl<'abi, T, const MAX: usize> OwnedVec<'abi, T, MAX> {
+    /// Construct a new owned vec argument from a slice, or None if it has more than MAX elements.
+    pub fn new(value: &'abi [T]) -> Option<Self> {
+        (value.len() <= MAX).then(|| Self {
+            slice: UserSlice::new(value),
+        })
+    }
+
+    /// Get the number of elements.
+    pub fn len(&self) -> usize {
+        self.slice.len()
+    }
+
+    /// Are there no elements?
+    pub fn is_empty(&self) -> bool {
+        self.slice.is_empty()
+    }
+}
+
+impl<'abi, T: Copy + Sync, const MAX: usize> OwnedVec<'abi, T, MAX> {
+    /// Verify the slice, and copy it into a new Vec. See [UserSlice::to_vec].
+    pub fn to_vec(
+        &self,
+        f: impl FnOnce(*const T, usize) -> bool,
+    ) -> Result<Vec<T>, OwnedCopyError> {
+        self.slice.to_vec(MAX, f)
+    }
+}
+
+impl<
+        'a,
+        Abi: SyscallAbi,
+        EncodedType: Copy,
+        Encoder: SyscallEncoder<'a, Abi, EncodedType>,
+        T: Copy,
+        const MAX: usize,
+    > SyscallEncodable<'a, Abi, EncodedType, Encoder> for OwnedVec<'a, T, MAX>
+{
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), EncodeError> {
+        self.slice.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Encoder) -> Result<Self, DecodeError>
+    where
+        Self: Sized,
+    {
+        let slice = UserSlice::<'a, T>::decode(decoder)?;
+        if slice.len() > MAX {
+            return Err(DecodeError::InvalidData);
+        }
+        Ok(Self { slice })
+    }
+}
+
+impl<'abi, T: AbiHash, const MAX: usize> AbiHash for OwnedVec<'abi, T, MAX> {
+    const ABI_HASH: u64 = hash_u64(
+        hash_u64(hash_str(HASH_SEED, "OwnedVec"), T::ABI_HASH),
+        MAX as u64,
+    );
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A string in user memory of at most MAX bytes, which the kernel copies into a [String]. See [OwnedVec].
+pub struct OwnedString<'abi, const MAX: usize> {
+    str: UserStr<'abi>,
+}
+
+impl<'abi, const MAX: usize> OwnedString<'abi, MAX> {
+    /// Construct a new owned string argument, or None if it is longer than MAX bytes.
+    pub fn new(value: &'abi str) -> Option<Self> {
+        (value.len() <= MAX).then(|| Self {
+            str: UserStr::new(value),
+        })
+    }
+
+    /// Verify the string, and copy it into a new String. See [UserStr::to_string].
+    pub fn to_string(
+        &self,
+        f: impl FnOnce(*const u8, usize) -> bool,
+    ) -> Result<String, OwnedCopyError> {
+        self.str.to_string(MAX, f)
+    }
+}
+
+impl<
+        'a,
+        Abi: SyscallAbi,
+        EncodedType: Copy,
+        Encoder: SyscallEncoder<'a, Abi, EncodedType>,
+        const MAX: usize,
+    > SyscallEncodable<'a, Abi, EncodedType, Encoder> for OwnedString<'a, MAX>
+{
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), EncodeError> {
+        self.str.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Encoder) -> Result<Self, DecodeError>
+    where
+        Self: Sized,
+    {
+        let str = UserStr::<'a>::decode(decoder)?;
+        if str.len() > MAX {
+            return Err(DecodeError::InvalidData);
+        }
+        Ok(Self { str })
+    }
+}
+
+impl<'abi, const MAX: usize> AbiHash for OwnedString<'abi, MAX> {
+    const ABI_HASH: u64 = hash_u64(hash_str(HASH_SEED, "OwnedString"), MAX as u64);
+}
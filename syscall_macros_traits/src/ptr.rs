@@ -1,11 +1,16 @@
-use core::marker::PhantomData;
+use core::{ffi::CStr, marker::PhantomData};
 
-use crate::{abi::SyscallAbi, api::SyscallEncodable, encoder::SyscallEncoder};
+use crate::{
+    abi::SyscallAbi,
+    api::SyscallEncodable,
+    encoder::SyscallEncoder,
+    fingerprint::{hash_str, hash_u64, AbiHash, HASH_SEED},
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 /// A type representing a user pointer.
 pub struct UserPointer<'abi, T> {
-    raw: usize,
+    pub(crate) raw: usize,
     _pd: PhantomData<&'abi T>,
 }
 
@@ -26,6 +31,13 @@ impl<'abi, T> UserPointer<'abi, T> {
         }
     }
 
+    pub(crate) fn from_raw(raw: usize) -> Self {
+        Self {
+            raw,
+            _pd: PhantomData,
+        }
+    }
+
     /// Is this a null user pointer?
     pub fn is_null(&self) -> bool {
         self.raw == 0
@@ -72,8 +84,8 @@ impl<'abi, T: Sync> UserPointer<'abi, T> {
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 /// A type representing a slice of user memory.
 pub struct UserSlice<'abi, T> {
-    ptr: UserPointer<'abi, T>,
-    len: usize,
+    pub(crate) ptr: UserPointer<'abi, T>,
+    pub(crate) len: usize,
 }
 
 impl<'abi, T> UserSlice<'abi, T> {
@@ -130,6 +142,303 @@ impl<'abi, T: Sync> UserSlice<'abi, T> {
     }
 }
 
+/// Errors that occur when the kernel accesses a user string.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Ord, Eq, Hash)]
+pub enum UserStrError {
+    /// The pointer was null, or the verification closure rejected the memory.
+    InvalidPointer,
+    /// The string does not fit in the supplied buffer.
+    TooLong,
+    /// No NUL terminator was found within the maximum length.
+    Unterminated,
+    /// The string is not valid UTF-8.
+    InvalidUtf8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A type representing a string in user memory, as a pointer and a length.
+pub struct UserStr<'abi> {
+    pub(crate) bytes: UserSlice<'abi, u8>,
+}
+
+impl<'abi> UserStr<'abi> {
+    /// Construct a new user string.
+    pub fn new(s: &'abi str) -> Self {
+        Self {
+            bytes: UserSlice::new(s.as_bytes()),
+        }
+    }
+
+    /// Get the length of the string, in bytes.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Is this string empty?
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Verify the string, and return it as a reference. See [UserPointer::as_ref]. Note that
+    /// the string is validated as UTF-8 in place, so userspace must not be able to modify it
+    /// concurrently. Prefer [Self::copy_into] if that cannot be guaranteed.
+    pub fn as_ref(
+        &self,
+        f: impl FnOnce(*const u8, usize) -> bool,
+    ) -> Result<&'abi str, UserStrError> {
+        let bytes = self.bytes.as_ref(f).ok_or(UserStrError::InvalidPointer)?;
+        core::str::from_utf8(bytes).map_err(|_| UserStrError::InvalidUtf8)
+    }
+
+    /// Verify the string, and copy it into a kernel buffer. The copy is validated as UTF-8, after
+    /// copying. See [UserPointer::as_ref] for the verification closure.
+    pub fn copy_into<'b>(
+        &self,
+        buf: &'b mut [u8],
+        f: impl FnOnce(*const u8, usize) -> bool,
+    ) -> Result<&'b str, UserStrError> {
+        if self.len() > buf.len() {
+            return Err(UserStrError::TooLong);
+        }
+        let src = self.bytes.as_ref(f).ok_or(UserStrError::InvalidPointer)?;
+        let dst = &mut buf[..src.len()];
+        dst.copy_from_slice(src);
+        core::str::from_utf8(dst).map_err(|_| UserStrError::InvalidUtf8)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A type representing a NUL-terminated string in user memory. Only the pointer is encoded, so the
+/// kernel must scan for the terminator, up to some maximum length.
+pub struct UserCStr<'abi> {
+    ptr: UserPointer<'abi, u8>,
+}
+
+/// Verification regions passed to the closure while scanning a [UserCStr] never cross a
+/// multiple of this value, so a string that ends before an unmapped page can be read.
+pub const CSTR_SCAN_CHUNK: usize = 4096;
+
+impl<'abi> UserCStr<'abi> {
+    /// Construct a new user C string.
+    pub fn new(s: &'abi CStr) -> Self {
+        // Safety: a CStr always contains at least the NUL terminator.
+        let first = unsafe { &*(s.as_ptr() as *const u8) };
+        Self {
+            ptr: UserPointer::new(first),
+        }
+    }
+
+    /// Construct a new null user C string.
+    pub fn new_null() -> Self {
+        Self {
+            ptr: UserPointer::new_null(),
+        }
+    }
+
+    /// Is this a null user C string?
+    pub fn is_null(&self) -> bool {
+        self.ptr.is_null()
+    }
+
+    /// Scan the string, calling sink on each byte up to and including the terminator. Returns the
+    /// length of the string, not including the terminator.
+    fn scan(
+        &self,
+        max_len: usize,
+        mut f: impl FnMut(*const u8, usize) -> bool,
+        mut sink: impl FnMut(usize, u8) -> Result<(), UserStrError>,
+    ) -> Result<usize, UserStrError> {
+        if self.is_null() {
+            return Err(UserStrError::InvalidPointer);
+        }
+        let mut off = 0;
+        while off < max_len {
+            let addr = self
+                .ptr
+                .raw
+                .checked_add(off)
+                .ok_or(UserStrError::InvalidPointer)?;
+            let chunk = (CSTR_SCAN_CHUNK - addr % CSTR_SCAN_CHUNK).min(max_len - off);
+            let ptr: *const u8 = core::ptr::from_exposed_addr(addr);
+            if !f(ptr, chunk) {
+                return Err(UserStrError::InvalidPointer);
+            }
+            for i in 0..chunk {
+                // Safety: the region was verified by the closure. The read is volatile since userspace
+                // may be modifying the memory.
+                let b = unsafe { ptr.add(i).read_volatile() };
+                sink(off + i, b)?;
+                if b == 0 {
+                    return Ok(off + i);
+                }
+            }
+            off += chunk;
+        }
+        Err(UserStrError::Unterminated)
+    }
+
+    /// Verify the string, scanning at most max_len bytes (including the terminator), and return it as a reference.
+    /// The closure may be called multiple times, once for each region that is scanned (see [CSTR_SCAN_CHUNK]),
+    /// and otherwise has the same requirements as in [UserPointer::as_ref]. Note that userspace must not be
+    /// able to modify the string concurrently. Prefer [Self::copy_into] if that cannot be guaranteed.
+    pub fn as_ref(
+        &self,
+        max_len: usize,
+        f: impl FnMut(*const u8, usize) -> bool,
+    ) -> Result<&'abi CStr, UserStrError> {
+        let len = self.scan(max_len, f, |_, _| Ok(()))?;
+        let ptr: *const u8 = core::ptr::from_exposed_addr(self.ptr.raw);
+        // Safety: we verified and scanned len + 1 bytes, the last of which is the terminator.
+        let bytes = unsafe { core::slice::from_raw_parts(ptr, len + 1) };
+        CStr::from_bytes_with_nul(bytes).map_err(|_| UserStrError::Unterminated)
+    }
+
+    /// Verify the string, scanning at most max_len bytes (including the terminator), and copy it into a
+    /// kernel buffer. See [Self::as_ref] for the verification closure.
+    pub fn copy_into<'b>(
+        &self,
+        buf: &'b mut [u8],
+        max_len: usize,
+        f: impl FnMut(*const u8, usize) -> bool,
+    ) -> Result<&'b CStr, UserStrError> {
+        let buflen = buf.len();
+        let len = self.scan(max_len, f, |off, b| {
+            if off >= buflen {
+                return Err(UserStrError::TooLong);
+            }
+            buf[off] = b;
+            Ok(())
+        })?;
+        CStr::from_bytes_with_nul(&buf[..(len + 1)]).map_err(|_| UserStrError::Unterminated)
+    }
+
+    /// Like [Self::copy_into], but additionally validates the copied string as UTF-8.
+    pub fn copy_str_into<'b>(
+        &self,
+        buf: &'b mut [u8],
+        max_len: usize,
+        f: impl FnMut(*const u8, usize) -> bool,
+    ) -> Result<&'b str, UserStrError> {
+        self.copy_into(buf, max_len, f)?
+            .to_str()
+            .map_err(|_| UserStrError::InvalidUtf8)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum ObjectTarget {
+    Local(usize),
+    Object { id: u128, offset: u64 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A type representing an object-relative pointer, as an object ID and a byte offset within that object. A pointer
+/// constructed from a local reference is translated by the ABI (see [SyscallAbi::addr_to_object]) when it is encoded.
+pub struct ObjectPointer<'abi, T> {
+    target: ObjectTarget,
+    _pd: PhantomData<&'abi T>,
+}
+
+impl<'abi, T> ObjectPointer<'abi, T> {
+    /// Construct a new object pointer from an object ID and a byte offset.
+    pub fn new(id: u128, offset: u64) -> Self {
+        Self {
+            target: ObjectTarget::Object { id, offset },
+            _pd: PhantomData,
+        }
+    }
+
+    /// Construct a new object pointer from a local reference, to be translated during encoding.
+    pub fn from_local(ptr: &'abi T) -> Self {
+        Self {
+            target: ObjectTarget::Local((ptr as *const T).expose_addr()),
+            _pd: PhantomData,
+        }
+    }
+
+    /// Construct a new null object pointer.
+    pub fn new_null() -> Self {
+        Self::new(0, 0)
+    }
+
+    /// Is this a null object pointer?
+    pub fn is_null(&self) -> bool {
+        matches!(self.target, ObjectTarget::Object { id: 0, .. })
+    }
+
+    /// Get the object ID, or None if this pointer has not been translated yet.
+    pub fn id(&self) -> Option<u128> {
+        match self.target {
+            ObjectTarget::Object { id, .. } => Some(id),
+            ObjectTarget::Local(_) => None,
+        }
+    }
+
+    /// Get the byte offset, or None if this pointer has not been translated yet.
+    pub fn offset(&self) -> Option<u64> {
+        match self.target {
+            ObjectTarget::Object { offset, .. } => Some(offset),
+            ObjectTarget::Local(_) => None,
+        }
+    }
+
+    /// Translate this pointer into a local virtual address, using the ABI (see [SyscallAbi::object_to_addr]).
+    pub fn to_local<Abi: SyscallAbi>(&self, abi: &Abi) -> Option<*const T> {
+        let addr = match self.target {
+            ObjectTarget::Local(addr) => addr,
+            ObjectTarget::Object { id, offset } => abi.object_to_addr(id, offset)?,
+        };
+        Some(core::ptr::from_exposed_addr(addr))
+    }
+}
+
+impl<'abi, T: Sync> ObjectPointer<'abi, T> {
+    // Bounds- and alignment-check the pointer against the object that f resolves its ID to, and return the address
+    // of the value, derived from the base address returned by f.
+    fn resolve_raw(&self, f: impl FnOnce(u128) -> Option<(*mut u8, usize)>) -> Option<*mut T> {
+        let ObjectTarget::Object { id, offset } = self.target else {
+            return None;
+        };
+        if id == 0 {
+            return None;
+        }
+        let (base, size) = f(id)?;
+        let offset = usize::try_from(offset).ok()?;
+        let end = offset.checked_add(core::mem::size_of::<T>())?;
+        if base.is_null() || end > size {
+            return None;
+        }
+        // Safety: the offset is within the object, which the closure promises is valid.
+        let ptr = unsafe { base.add(offset) } as *mut T;
+        if !ptr.is_aligned() {
+            return None;
+        }
+        Some(ptr)
+    }
+
+    /// Resolve the object, and return the pointed-to value as a reference. Should be called by the kernel before
+    /// using the pointer. The supplied closure should look up the object ID, check that the caller may access the
+    /// object, and return the object's base address and size. The offset is then bounds-checked against the size,
+    /// and checked for alignment. See [UserPointer::as_ref] for the other requirements.
+    pub fn resolve(&self, f: impl FnOnce(u128) -> Option<(*const u8, usize)>) -> Option<&'abi T> {
+        // The pointer is only used for reading, so casting the base to *mut is fine.
+        let ptr = self.resolve_raw(|id| f(id).map(|(base, size)| (base as *mut u8, size)))?;
+        // Safety: the closure verified the object, and we checked bounds and alignment.
+        unsafe { (ptr as *const T).as_ref() }
+    }
+
+    /// See [Self::resolve]. The closure must return a base address that is valid for writes.
+    pub fn resolve_mut(
+        &mut self,
+        f: impl FnOnce(u128) -> Option<(*mut u8, usize)>,
+    ) -> Option<&'abi mut T> {
+        let ptr = self.resolve_raw(f)?;
+        // Safety: the closure verified the object and returned a writable base address, and we checked bounds and
+        // alignment.
+        unsafe { ptr.as_mut() }
+    }
+}
+
 impl<'abi, T> From<&'abi T> for UserPointer<'abi, T> {
     fn from(value: &'abi T) -> Self {
         Self::new(value)
@@ -154,6 +463,18 @@ impl<'abi, T> From<&'abi mut [T]> for UserSlice<'abi, T> {
     }
 }
 
+impl<'abi> From<&'abi str> for UserStr<'abi> {
+    fn from(value: &'abi str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<'abi> From<&'abi CStr> for UserCStr<'abi> {
+    fn from(value: &'abi CStr) -> Self {
+        Self::new(value)
+    }
+}
+
 impl<
         'a,
         Abi: SyscallAbi,
@@ -200,3 +521,87 @@ impl<
         })
     }
 }
+
+impl<'a, Abi: SyscallAbi, EncodedType: Copy, Encoder: SyscallEncoder<'a, Abi, EncodedType>>
+    SyscallEncodable<'a, Abi, EncodedType, Encoder> for UserStr<'a>
+{
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), crate::encoder::EncodeError> {
+        self.bytes.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Encoder) -> Result<Self, crate::encoder::DecodeError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            bytes: UserSlice::<'a, u8>::decode(decoder)?,
+        })
+    }
+}
+
+impl<'a, Abi: SyscallAbi, EncodedType: Copy, Encoder: SyscallEncoder<'a, Abi, EncodedType>>
+    SyscallEncodable<'a, Abi, EncodedType, Encoder> for UserCStr<'a>
+{
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), crate::encoder::EncodeError> {
+        self.ptr.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Encoder) -> Result<Self, crate::encoder::DecodeError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            ptr: UserPointer::<'a, u8>::decode(decoder)?,
+        })
+    }
+}
+
+impl<
+        'a,
+        Abi: SyscallAbi,
+        EncodedType: Copy,
+        Encoder: SyscallEncoder<'a, Abi, EncodedType>,
+        T: Copy,
+    > SyscallEncodable<'a, Abi, EncodedType, Encoder> for ObjectPointer<'a, T>
+{
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), crate::encoder::EncodeError> {
+        let (id, offset) = match self.target {
+            ObjectTarget::Local(addr) => encoder
+                .abi()
+                .and_then(|abi| abi.addr_to_object(addr))
+                .ok_or(crate::encoder::EncodeError::UntranslatableAddress)?,
+            ObjectTarget::Object { id, offset } => (id, offset),
+        };
+        id.encode(encoder)?;
+        offset.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Encoder) -> Result<Self, crate::encoder::DecodeError>
+    where
+        Self: Sized,
+    {
+        Ok(Self::new(u128::decode(decoder)?, u64::decode(decoder)?))
+    }
+}
+
+// The kernel reads pointed-to types from memory, so their layout is part of the ABI even though they are not
+// encoded. Types that point to themselves, directly or not, can't be hashed this way.
+impl<'abi, T: AbiHash> AbiHash for UserPointer<'abi, T> {
+    const ABI_HASH: u64 = hash_u64(hash_str(HASH_SEED, "UserPointer"), T::ABI_HASH);
+}
+
+impl<'abi, T: AbiHash> AbiHash for UserSlice<'abi, T> {
+    const ABI_HASH: u64 = hash_u64(hash_str(HASH_SEED, "UserSlice"), T::ABI_HASH);
+}
+
+impl<'abi> AbiHash for UserStr<'abi> {
+    const ABI_HASH: u64 = hash_str(HASH_SEED, "UserStr");
+}
+
+impl<'abi> AbiHash for UserCStr<'abi> {
+    const ABI_HASH: u64 = hash_str(HASH_SEED, "UserCStr");
+}
+
+impl<'abi, T: AbiHash> AbiHash for ObjectPointer<'abi, T> {
+    const ABI_HASH: u64 = hash_u64(hash_str(HASH_SEED, "ObjectPointer"), T::ABI_HASH);
+}
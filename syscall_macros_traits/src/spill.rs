@@ -0,0 +1,337 @@
+//! Memory for arguments that spill out of registers. [SyscallAbi::with_alloc] implementations can delegate to one
+//! of the strategies here, rather than finding memory of their own. For example:
+//!
+//! ```no_compile
+//! fn with_alloc<F, R, E: Copy>(&self, layout: Layout, f: F) -> Result<R, SyscallError<E>>
+//! where
+//!     F: FnOnce(Allocation<'_>) -> Result<R, SyscallError<E>>,
+//! {
+//!     StackSpill::<256>.with_spill(layout, f)
+//! }
+//! ```
+
+use core::{
+    alloc::Layout,
+    cell::{Cell, UnsafeCell},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    abi::{Allocation, SyscallAbi},
+    error::SyscallError,
+};
+
+/// Provides the memory that a call's arguments spill into when they don't fit in registers. See
+/// [crate::api::SyscallApi::perform_call_with].
+pub trait SpillAllocator {
+    /// Allocate memory with the given layout, and pass it to f. Fails with [SyscallError::BufferTooSmall] if there
+    /// isn't enough memory.
+    fn with_spill<F, R, E: Copy>(&mut self, layout: Layout, f: F) -> Result<R, SyscallError<E>>
+    where
+        F: FnOnce(Allocation<'_>) -> Result<R, SyscallError<E>>;
+}
+
+/// Spills into memory from the ABI, with [SyscallAbi::with_alloc]. This is what
+/// [crate::api::SyscallApi::perform_call] uses.
+pub struct AbiSpill<'a, Abi>(pub &'a Abi);
+
+impl<Abi: SyscallAbi> SpillAllocator for AbiSpill<'_, Abi> {
+    fn with_spill<F, R, E: Copy>(&mut self, layout: Layout, f: F) -> Result<R, SyscallError<E>>
+    where
+        F: FnOnce(Allocation<'_>) -> Result<R, SyscallError<E>>,
+    {
+        self.0.with_alloc(layout, f)
+    }
+}
+
+/// Spills into the start of the slice.
+impl SpillAllocator for [u8] {
+    fn with_spill<F, R, E: Copy>(&mut self, layout: Layout, f: F) -> Result<R, SyscallError<E>>
+    where
+        F: FnOnce(Allocation<'_>) -> Result<R, SyscallError<E>>,
+    {
+        check_fits(self.as_ptr(), self.len(), layout)?;
+        f(Allocation::from(self))
+    }
+}
+
+// Returns the offset of the first byte aligned for layout, or the number of bytes needed if len is too small.
+fn check_fits<E: Copy>(
+    data: *const u8,
+    len: usize,
+    layout: Layout,
+) -> Result<usize, SyscallError<E>> {
+    let offset = data.align_offset(layout.align());
+    let needed = offset.saturating_add(layout.size());
+    if len < needed {
+        return Err(SyscallError::BufferTooSmall(needed));
+    }
+    Ok(offset)
+}
+
+#[repr(C, align(16))]
+struct AlignedBytes<const N: usize>([u8; N]);
+
+/// Spills into a buffer of N bytes on the stack, allocated for each call.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StackSpill<const N: usize>;
+
+impl<const N: usize> SpillAllocator for StackSpill<N> {
+    fn with_spill<F, R, E: Copy>(&mut self, layout: Layout, f: F) -> Result<R, SyscallError<E>>
+    where
+        F: FnOnce(Allocation<'_>) -> Result<R, SyscallError<E>>,
+    {
+        let mut buf = AlignedBytes([0u8; N]);
+        buf.0[..].with_spill(layout, f)
+    }
+}
+
+/// A spill buffer of N bytes that is reused across calls, e.g. kept in a thread-local, so that calls in hot loops
+/// or in environments without alloca don't need to find memory of their own. For example:
+///
+/// ```no_compile
+/// thread_local! {
+///     static SCRATCH: ScratchBuffer<256> = const { ScratchBuffer::new() };
+/// }
+///
+/// SCRATCH.with(|mut scratch| foo.perform_call_with(&abi, &mut scratch))
+/// ```
+///
+/// Only one call may use the buffer at a time. A call made while it is in use, e.g. from a signal handler that
+/// interrupted another call, fails with [SyscallError::AllocationError].
+pub struct ScratchBuffer<const N: usize> {
+    in_use: AtomicBool,
+    buf: UnsafeCell<AlignedBytes<N>>,
+}
+
+// Safety: the buffer is only accessed while in_use is held.
+unsafe impl<const N: usize> Sync for ScratchBuffer<N> {}
+
+impl<const N: usize> ScratchBuffer<N> {
+    /// Create a new, zeroed buffer.
+    pub const fn new() -> Self {
+        Self {
+            in_use: AtomicBool::new(false),
+            buf: UnsafeCell::new(AlignedBytes([0; N])),
+        }
+    }
+
+    /// Is a call using the buffer?
+    pub fn in_use(&self) -> bool {
+        self.in_use.load(Ordering::Relaxed)
+    }
+}
+
+impl<const N: usize> Default for ScratchBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Releases a ScratchBuffer or SharedPage when the call using it finishes, even if it panics.
+struct Release<'a>(&'a AtomicBool);
+
+impl Drop for Release<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+impl<'a> Release<'a> {
+    fn acquire<E: Copy>(in_use: &'a AtomicBool) -> Result<Self, SyscallError<E>> {
+        if in_use.swap(true, Ordering::Acquire) {
+            return Err(SyscallError::AllocationError);
+        }
+        Ok(Self(in_use))
+    }
+}
+
+impl<const N: usize> SpillAllocator for &ScratchBuffer<N> {
+    fn with_spill<F, R, E: Copy>(&mut self, layout: Layout, f: F) -> Result<R, SyscallError<E>>
+    where
+        F: FnOnce(Allocation<'_>) -> Result<R, SyscallError<E>>,
+    {
+        let _release = Release::acquire(&self.in_use)?;
+        // Safety: we hold in_use until _release is dropped, so nothing else is using the buffer.
+        let buf = unsafe { &mut (*self.buf.get()).0 };
+        buf[..].with_spill(layout, f)
+    }
+}
+
+impl<const N: usize> SpillAllocator for ScratchBuffer<N> {
+    fn with_spill<F, R, E: Copy>(&mut self, layout: Layout, f: F) -> Result<R, SyscallError<E>>
+    where
+        F: FnOnce(Allocation<'_>) -> Result<R, SyscallError<E>>,
+    {
+        (&*self).with_spill(layout, f)
+    }
+}
+
+/// A per-thread arena of N bytes that calls bump-allocate their spill from, e.g. kept in a thread-local. Unlike a
+/// [ScratchBuffer], calls may nest, e.g. in a signal handler that interrupted another call, as long as the arena
+/// has room for all of them. Each call's memory is freed when it returns.
+pub struct BumpArena<const N: usize> {
+    top: Cell<usize>,
+    buf: UnsafeCell<AlignedBytes<N>>,
+}
+
+impl<const N: usize> BumpArena<N> {
+    /// Create a new, zeroed arena.
+    pub const fn new() -> Self {
+        Self {
+            top: Cell::new(0),
+            buf: UnsafeCell::new(AlignedBytes([0; N])),
+        }
+    }
+
+    /// The number of bytes allocated by calls in progress.
+    pub fn used(&self) -> usize {
+        self.top.get()
+    }
+}
+
+impl<const N: usize> Default for BumpArena<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Frees a call's memory from a BumpArena when the call finishes, even if it panics.
+struct Pop<'a>(&'a Cell<usize>, usize);
+
+impl Drop for Pop<'_> {
+    fn drop(&mut self) {
+        self.0.set(self.1);
+    }
+}
+
+impl<const N: usize> SpillAllocator for &BumpArena<N> {
+    fn with_spill<F, R, E: Copy>(&mut self, layout: Layout, f: F) -> Result<R, SyscallError<E>>
+    where
+        F: FnOnce(Allocation<'_>) -> Result<R, SyscallError<E>>,
+    {
+        let top = self.top.get();
+        // Safety: top never exceeds N, so the pointer stays within the buffer.
+        let free = unsafe { (self.buf.get() as *mut u8).add(top) };
+        let offset = free.align_offset(layout.align());
+        let end = top.saturating_add(offset).saturating_add(layout.size());
+        if end > N {
+            return Err(SyscallError::BufferTooSmall(end));
+        }
+        self.top.set(end);
+        let _pop = Pop(&self.top, top);
+        // Safety: the memory is within the buffer, and is not handed out again until _pop is dropped. Nested calls
+        // only allocate past it.
+        f(unsafe { Allocation::from_raw_parts(free.add(offset), layout.size()) })
+    }
+}
+
+impl<const N: usize> SpillAllocator for BumpArena<N> {
+    fn with_spill<F, R, E: Copy>(&mut self, layout: Layout, f: F) -> Result<R, SyscallError<E>>
+    where
+        F: FnOnce(Allocation<'_>) -> Result<R, SyscallError<E>>,
+    {
+        (&*self).with_spill(layout, f)
+    }
+}
+
+/// Spills into a region of memory that is mapped by the kernel, e.g. a page shared between the application and the
+/// kernel, so that the kernel can read arguments without copying them in. Like a [ScratchBuffer], only one call
+/// may use the region at a time, and a call made while it is in use fails with [SyscallError::AllocationError].
+pub struct SharedPage {
+    base: *mut u8,
+    size: usize,
+    in_use: AtomicBool,
+}
+
+// Safety: the region is only accessed while in_use is held.
+unsafe impl Send for SharedPage {}
+unsafe impl Sync for SharedPage {}
+
+impl SharedPage {
+    /// Spill into the size bytes at base.
+    ///
+    /// # Safety
+    /// The memory must be valid for reads and writes of size bytes, initialized, and not otherwise accessed by the
+    /// application, for as long as the SharedPage exists.
+    pub const unsafe fn new(base: *mut u8, size: usize) -> Self {
+        Self {
+            base,
+            size,
+            in_use: AtomicBool::new(false),
+        }
+    }
+
+    /// The start of the region.
+    pub fn base(&self) -> *mut u8 {
+        self.base
+    }
+
+    /// The size of the region, in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Is a call using the region?
+    pub fn in_use(&self) -> bool {
+        self.in_use.load(Ordering::Relaxed)
+    }
+}
+
+impl SpillAllocator for &SharedPage {
+    fn with_spill<F, R, E: Copy>(&mut self, layout: Layout, f: F) -> Result<R, SyscallError<E>>
+    where
+        F: FnOnce(Allocation<'_>) -> Result<R, SyscallError<E>>,
+    {
+        let _release = Release::acquire(&self.in_use)?;
+        let offset = check_fits(self.base, self.size, layout)?;
+        // Safety: we hold in_use until _release is dropped, and the region is valid, as promised to new().
+        f(unsafe { Allocation::from_raw_parts(self.base.add(offset), self.size - offset) })
+    }
+}
+
+impl SpillAllocator for SharedPage {
+    fn with_spill<F, R, E: Copy>(&mut self, layout: Layout, f: F) -> Result<R, SyscallError<E>>
+    where
+        F: FnOnce(Allocation<'_>) -> Result<R, SyscallError<E>>,
+    {
+        (&*self).with_spill(layout, f)
+    }
+}
+
+/// Spills into memory from the global allocator, allocated for each call.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HeapSpill;
+
+// Frees a HeapSpill allocation when the call finishes, even if it panics.
+#[cfg(feature = "alloc")]
+struct Dealloc(*mut u8, Layout);
+
+#[cfg(feature = "alloc")]
+impl Drop for Dealloc {
+    fn drop(&mut self) {
+        // Safety: the memory was allocated with this layout.
+        unsafe { alloc::alloc::dealloc(self.0, self.1) }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl SpillAllocator for HeapSpill {
+    fn with_spill<F, R, E: Copy>(&mut self, layout: Layout, f: F) -> Result<R, SyscallError<E>>
+    where
+        F: FnOnce(Allocation<'_>) -> Result<R, SyscallError<E>>,
+    {
+        // The global allocator doesn't support zero-sized allocations.
+        let layout = Layout::from_size_align(layout.size().max(1), layout.align())
+            .map_err(|_| SyscallError::AllocationError)?;
+        // Safety: the layout has a non-zero size.
+        let data = unsafe { alloc::alloc::alloc_zeroed(layout) };
+        if data.is_null() {
+            return Err(SyscallError::AllocationError);
+        }
+        let _dealloc = Dealloc(data, layout);
+        // Safety: the memory is zeroed, and freed only after f returns.
+        f(unsafe { Allocation::from_raw_parts(data, layout.size()) })
+    }
+}
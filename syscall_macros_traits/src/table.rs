@@ -1,4 +1,9 @@
-use crate::abi::SyscallAbi;
+use core::{alloc::Layout, future::Future};
+
+use crate::{abi::SyscallAbi, api::SyscallEncodable, encoder::SyscallEncoder, error::SyscallError};
+
+#[cfg(feature = "dyn-table")]
+pub mod dynamic;
 
 /// Define the entire syscall table based on types that implement SyscallApi and SyscallFastApi. Also
 /// acts as the match statement for that table, and so takes in the syscall number and args for the syscall
@@ -25,12 +30,13 @@ use crate::abi::SyscallAbi;
 ///         abi_type = X86Abi;
 ///         // An instance of that ABI.
 ///         abi = abi;
-///         // List of handlers.
+///         // List of handlers. Each may list older versions of its syscall that are still accepted,
+///         // which must implement Into<Foo>.
 ///         handlers = {
 ///             (Foo, |num, foo| {
 ///                 ...;
 ///                 Ok(FooRet{...})
-///             }), ...
+///             }, upgrades = [FooV1, FooV0]), ...
 ///         }
 ///         // List of handlers that use the Fast API.
 ///         fast_handlers = {
@@ -43,6 +49,35 @@ use crate::abi::SyscallAbi;
 /// }
 /// ```
 ///
+/// The version of an incoming call is split from its number with [SyscallAbi::split_versioned_num]. Calls with
+/// versions newer than the handler's type fail with [crate::error::SyscallError::VersionTooNew], and calls with
+/// older versions that are not listed in upgrades fail with [crate::error::SyscallError::VersionTooOld].
+///
+/// The table fails to compile if any two of its handlers, fast or not, share a NUM (see
+/// [crate::assert_unique_syscall_nums]).
+///
+/// Handlers may also be given a context, such as the calling thread, by passing `context = ctx;` (a mutable
+/// reference) after the abi. Each handler then takes the context as its first argument, e.g.
+/// `|ctx, num, foo| ...`.
+///
+/// Handlers of syscalls that block may be interrupted, e.g. by a signal or upcall. Such handlers are marked with
+/// `restartable = true` after any upgrades, and return a [crate::error::SyscallError] rather than the syscall's
+/// error type, so that they can report [crate::error::SyscallError::Interrupted], or
+/// [crate::error::SyscallError::Restart] along with any state they need to pick up where they left off. What the
+/// caller does next is up to the syscall's [crate::api::SyscallApi::RESTART_POLICY].
+///
+/// By default, handlers return their result directly. Tables whose handlers may have to wait for the result
+/// instead pass `mode = blocking;` or `mode = async;` after the abi and context:
+///  - In blocking mode, handlers return a [Blocking] of their result and a wait object, and so does the table,
+///    with the result encoded as usual. A call that blocks is not finished: the kernel waits on the wait object,
+///    e.g. by parking the thread on a wait queue, and then makes the call to the table again, with the same
+///    number and arguments. Handlers that make progress before blocking may save it in the context. See
+///    [BlockingSyscallTable].
+///  - In async mode, handlers return a future of their result, and the table is an async block that
+///    resolves to the encoded result. Handlers may use the context to set up their future, but the future cannot
+///    borrow it. See [AsyncSyscallTable].
+///
+/// Fast handlers return their result directly in every mode.
 #[macro_export]
 macro_rules! syscall_api {
     (
@@ -50,72 +85,403 @@ macro_rules! syscall_api {
         args = $in_args:expr;
         abi_type = $abitype:ty;
         abi = $abi:expr;
-        handlers = { $(($type:ty, $call:expr)),* }
+        $(mode = $mode:tt;)?
+        handlers = { $(($type:ty, $call:expr $(, upgrades = [$($old:ty),* $(,)?])? $(, restartable = $restartable:tt)?)),* }
+        fast_handlers = { $(($fasttype:ty, $fastcall:expr)),* }
+    ) => {
+        $crate::syscall_api! {
+            number = $in_num;
+            args = $in_args;
+            abi_type = $abitype;
+            abi = $abi;
+            context = &mut ();
+            $(mode = $mode;)?
+            handlers = { $(($type, $crate::table::__ignore_context::<_, $type, _>($call) $(, upgrades = [$($old),*])? $(, restartable = $restartable)?)),* }
+            fast_handlers = { $(($fasttype, $crate::table::__ignore_context::<_, $fasttype, _>($fastcall))),* }
+        }
+    };
+    (
+        number = $in_num:expr;
+        args = $in_args:expr;
+        abi_type = $abitype:ty;
+        abi = $abi:expr;
+        context = $ctx:expr;
+        $(mode = $mode:tt;)?
+        handlers = { $(($type:ty, $call:expr $(, upgrades = [$($old:ty),* $(,)?])? $(, restartable = $restartable:tt)?)),* }
+        fast_handlers = { $(($fasttype:ty, $fastcall:expr)),* }
+    ) => {
+        $crate::syscall_api! {
+            @table
+            number = $in_num;
+            args = $in_args;
+            abi_type = $abitype;
+            abi = $abi;
+            context = $ctx;
+            mode = [$($mode)?];
+            handlers = { $(($type, $call $(, upgrades = [$($old),*])? $(, restartable = $restartable)?)),* }
+            fast_handlers = { $(($fasttype, $fastcall)),* }
+        }
+    };
+    (
+        @table
+        number = $in_num:expr;
+        args = $in_args:expr;
+        abi_type = $abitype:ty;
+        abi = $abi:expr;
+        context = $ctx:expr;
+        mode = $mode:tt;
+        handlers = { $(($type:ty, $call:expr $(, upgrades = [$($old:ty),*])? $(, restartable = $restartable:tt)?)),* }
         fast_handlers = { $(($fasttype:ty, $fastcall:expr)),* }
     ) => {
         {
-        use syscall_encode_traits::encoder::SyscallEncoder;
-        use syscall_encode_traits::api::SyscallEncodable;
-        let res = match $in_num {
+        $crate::assert_unique_syscall_nums! {
+            abi_type = $abitype;
+            handlers = { $($type),* }
+            fast_handlers = { $($fasttype),* }
+        }
+        let ctx = $ctx;
+        let (num, version) = $abi.split_versioned_num($in_num);
+        $crate::__table_mode!($mode body {
+        match num {
             $(
                 <$type as SyscallApi<$abitype>>::NUM => {
-                    let r = <$type as SyscallApi<$abitype>>::with($abi, $in_num, $in_args, $call);
-
-                    let layout = core::alloc::Layout::new::<
-                        Result<
-                            <$type as SyscallApi<'a, $abitype>>::ReturnType,
-                            SyscallError<<$type as SyscallApi<'a, $abitype>>::ErrorType>,
-                        >,
-                    >();
-
-                    let alloc = $abi.kernel_alloc(layout);
-                    let mut encoder = $abi.ret_encoder(alloc);
-
-                    if r.encode(&mut encoder).is_err() {
-                        $abi.unrecoverable_encoding_failure(r)
+                    let current = <$type as SyscallApi<$abitype>>::VERSION;
+                    let call = $crate::table::__with_context::<_, _, $type, _>(&mut *ctx, $call);
+                    let args = if version == current {
+                        <$type as SyscallApi<$abitype>>::decode_call($abi, num, $in_args)
+                    }
+                    $($(
+                    else if version == <$old as SyscallApi<$abitype>>::VERSION {
+                        <$type as SyscallApi<$abitype>>::decode_upgraded_call::<$old>($abi, num, $in_args)
                     }
-                    let s = encoder.finish();
-                    s
+                    )*)?
+                    else if version > current {
+                        Err(SyscallError::VersionTooNew)
+                    } else {
+                        Err(SyscallError::VersionTooOld)
+                    };
+                    $crate::__table_mode!($mode call(call, num, args, $crate::__handler_errors!($($restartable)?), $abi))
                 },
             )*
             $(
                 <$fasttype as SyscallFastApi<$abitype>>::NUM => {
                     let args: $fasttype = $in_args.into();
-                    let ret = $fastcall(<$fasttype as SyscallFastApi<$abitype>>::NUM, args);
-                    ret.into()
+                    let ret = $crate::table::__with_context::<_, _, $fasttype, _>(&mut *ctx, $fastcall)(<$fasttype as SyscallFastApi<$abitype>>::NUM, args);
+                    $crate::__table_mode!($mode ready(ret.into()))
                 }
             )*
-            _ => {
-                    let layout = core::alloc::Layout::new::<
-                        Result<
-                            (),
-                            SyscallError<()>,
-                        >,
-                    >();
-                    let alloc = $abi.kernel_alloc(layout);
-                    let mut encoder = $abi.ret_encoder(alloc);
-
-                    let e: Result<(), SyscallError<()>> = Err(SyscallError::InvalidNum);
-                    if e.encode(&mut encoder).is_err() {
-                        $abi.unrecoverable_encoding_failure(e)
-                    }
-                    let s = encoder.finish();
-                    s
-               }
+            _ => $crate::__table_mode!($mode ready($crate::table::__invalid_num($abi))),
+        }
+        })
+    }
+    };
+}
+
+// How a table calls its handlers and hands back the result, in each mode of syscall_api.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __table_mode {
+    ([] body $body:block) => {
+        $body
+    };
+    ([blocking] body $body:block) => {
+        $body
+    };
+    ([async] body $body:block) => {
+        async move $body
+    };
+    ([] ready($ret:expr)) => {
+        $ret
+    };
+    ([blocking] ready($ret:expr)) => {
+        $crate::table::Blocking::Ready($ret)
+    };
+    ([async] ready($ret:expr)) => {
+        $ret
+    };
+    ([] call($call:ident, $num:ident, $args:ident, $errors:expr, $abi:expr)) => {
+        $crate::table::__encode_ret($abi, $args.and_then(|args| $errors($call($num, args))))
+    };
+    ([blocking] call($call:ident, $num:ident, $args:ident, $errors:expr, $abi:expr)) => {
+        match $args {
+            Ok(args) => $call($num, args).map($errors),
+            Err(e) => $crate::table::Blocking::Ready(Err(e)),
+        }
+        .map(|r| $crate::table::__encode_ret($abi, r))
+    };
+    ([async] call($call:ident, $num:ident, $args:ident, $errors:expr, $abi:expr)) => {
+        $crate::table::__encode_ret(
+            $abi,
+            match $args {
+                Ok(args) => $errors($call($num, args).await),
+                Err(e) => Err(e),
+            },
+        )
+    };
+}
+
+/// Fail to compile if any two of the given syscall types share a NUM, naming both types. [syscall_api] does
+/// this for its handlers, but crates that contribute syscalls to a table defined elsewhere can use this to check
+/// their syscalls against each other, and against the syscalls of other crates. NUMs are compared by matching, so
+/// this works for any [SyscallAbi::SyscallNumType] that a table can match on. For example:
+///
+/// ```no_compile
+/// assert_unique_syscall_nums! {
+///     abi_type = X86Abi;
+///     handlers = { Foo, Bar }
+///     fast_handlers = { FastFoo }
+/// }
+/// ```
+#[macro_export]
+macro_rules! assert_unique_syscall_nums {
+    (
+        abi_type = $abitype:ty;
+        handlers = { $($type:ty),* $(,)? }
+        fast_handlers = { $($fasttype:ty),* $(,)? }
+    ) => {
+        const _: () = {
+            $crate::__assert_unique_consts!(
+                "syscalls", NUM;
+                $(($type, $crate::api::SyscallApi<$abitype>),)*
+                $(($fasttype, $crate::api::SyscallFastApi<$abitype>),)*
+            );
         };
+    };
+}
 
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __assert_unique_consts {
+    ($kind:literal, $what:ident;) => {};
+    ($kind:literal, $what:ident; ($type:ty, $trait:path), $(($rest_type:ty, $rest_trait:path),)*) => {
+        // Compared by matching rather than ==, which isn't available in a const context for most types. This
+        // asks no more of the constants than the table's own match does.
+        $(
+            match <$type as $trait>::$what {
+                <$rest_type as $rest_trait>::$what => panic!(concat!(
+                    $kind,
+                    " ",
+                    stringify!($type),
+                    " and ",
+                    stringify!($rest_type),
+                    " have the same ",
+                    stringify!($what)
+                )),
+                _ => {}
+            }
+        )*
+        $crate::__assert_unique_consts!($kind, $what; $(($rest_type, $rest_trait),)*);
+    };
+}
+
+/// A range of syscall numbers that share a prefix, under which a subsystem's syscall table is mounted with
+/// [crate::syscall_mounts]. The syscalls of that table should all have numbers within the namespace.
+pub trait SyscallNamespace<Abi: SyscallAbi> {
+    /// The prefix of every syscall number in this namespace.
+    const PREFIX: Abi::SyscallNumType;
+}
+
+/// Compose a syscall table out of other tables, each mounted under a [SyscallNamespace]. The prefix of the
+/// incoming syscall number (with its version split off) selects the table that handles the call, and calls with
+/// prefixes that are not mounted fail with [crate::error::SyscallError::InvalidNum]. Mounted tables may
+/// themselves be composed of mounts, and are handed the number unchanged. For example:
+///
+/// ```no_compile
+/// fn handle(&self, ctx: &mut Thread, num: NumType, args: ArgType) -> RetType {
+///     syscall_mounts! {
+///         number = num;
+///         args = args;
+///         abi_type = X86Abi;
+///         abi = &self.abi;
+///         // Optional, as in syscall_api. Every mounted table must take the same context.
+///         context = ctx;
+///         // How to get the prefix from a syscall number.
+///         prefix = |num| num >> 16;
+///         // Namespaces, and the tables mounted under them, which must implement SyscallTable.
+///         mounts = {
+///             (FsNamespace, &self.fs),
+///             (NetNamespace, &self.net),
+///         }
+///     }
+/// }
+/// ```
+///
+/// Like [crate::syscall_api], this fails to compile if any two namespaces share a PREFIX.
+#[macro_export]
+macro_rules! syscall_mounts {
+    (
+        number = $in_num:expr;
+        args = $in_args:expr;
+        abi_type = $abitype:ty;
+        abi = $abi:expr;
+        prefix = $prefix:expr;
+        mounts = { $(($ns:ty, $table:expr)),* $(,)? }
+    ) => {
+        $crate::syscall_mounts! {
+            number = $in_num;
+            args = $in_args;
+            abi_type = $abitype;
+            abi = $abi;
+            context = &mut ();
+            prefix = $prefix;
+            mounts = { $(($ns, $table)),* }
+        }
+    };
+    (
+        number = $in_num:expr;
+        args = $in_args:expr;
+        abi_type = $abitype:ty;
+        abi = $abi:expr;
+        context = $ctx:expr;
+        prefix = $prefix:expr;
+        mounts = { $(($ns:ty, $table:expr)),* $(,)? }
+    ) => {
+        {
+            const _: () = {
+                $crate::__assert_unique_consts!(
+                    "namespaces", PREFIX;
+                    $(($ns, $crate::table::SyscallNamespace<$abitype>),)*
+                );
+            };
+            let ctx = $ctx;
+            let num = $in_num;
+            let args = $in_args;
+            let abi: &$abitype = $abi;
+            let (base, _) = $crate::abi::SyscallAbi::split_versioned_num(abi, num);
+            match ($prefix)(base) {
+                $(
+                    <$ns as $crate::table::SyscallNamespace<$abitype>>::PREFIX => {
+                        $crate::table::SyscallTable::<$abitype, _>::handle_call($table, ctx, num, args)
+                    }
+                )*
+                _ => $crate::table::__invalid_num(abi),
+            }
+        }
+    };
+}
 
+#[doc(hidden)]
+pub fn __invalid_num<Abi: SyscallAbi>(abi: &Abi) -> Abi::SyscallRetType {
+    __encode_ret(abi, Err::<(), SyscallError<()>>(SyscallError::InvalidNum))
+}
 
-        res
+#[doc(hidden)]
+pub fn __encode_ret<'a, Abi: SyscallAbi, R>(abi: &'a Abi, r: R) -> Abi::SyscallRetType
+where
+    R: for<'buf> SyscallEncodable<'a, Abi, Abi::SyscallRetType, Abi::RetEncoder<'a, 'buf>>,
+{
+    // Safety: the encoded value is handed straight back to the application.
+    let alloc = unsafe { abi.kernel_alloc(Layout::new::<R>()) };
+    let mut encoder = abi.ret_encoder(alloc);
+    if r.encode(&mut encoder).is_err() {
+        abi.unrecoverable_encoding_failure(r)
     }
+    encoder.finish()
+}
+
+#[doc(hidden)]
+pub fn __with_context<'c, Ctx: ?Sized, N, T, R>(
+    ctx: &'c mut Ctx,
+    f: impl FnOnce(&mut Ctx, N, T) -> R + 'c,
+) -> impl FnOnce(N, T) -> R + 'c {
+    move |num, args| f(ctx, num, args)
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __handler_errors {
+    () => {
+        $crate::table::__syscall_errors
     };
+    (false) => {
+        $crate::table::__syscall_errors
+    };
+    (true) => {
+        $crate::table::__restartable_errors
+    };
+}
+
+#[doc(hidden)]
+pub fn __syscall_errors<R, E: Copy>(r: Result<R, E>) -> Result<R, SyscallError<E>> {
+    r.map_err(SyscallError::SyscallError)
+}
+
+#[doc(hidden)]
+pub fn __restartable_errors<R, E: Copy>(
+    r: Result<R, SyscallError<E>>,
+) -> Result<R, SyscallError<E>> {
+    r
+}
+
+#[doc(hidden)]
+pub fn __ignore_context<N, T, R>(f: impl FnOnce(N, T) -> R) -> impl FnOnce(&mut (), N, T) -> R {
+    move |_, num, args| f(num, args)
 }
 
-/// Defines a function for handling incoming syscalls, before decoding.
-pub trait SyscallTable<Abi: SyscallAbi> {
+/// Defines a function for handling incoming syscalls, before decoding. Handlers are given ctx, which carries
+/// whatever they need to know about the caller, e.g. the calling thread.
+pub trait SyscallTable<Abi: SyscallAbi, Ctx: ?Sized = ()> {
     fn handle_call(
         &self,
+        ctx: &mut Ctx,
         num: Abi::SyscallNumType,
         arg: Abi::SyscallArgType,
     ) -> Abi::SyscallRetType;
 }
+
+/// The outcome of a call that may block, in the blocking mode of [crate::syscall_api].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Blocking<T, W> {
+    /// The call finished.
+    Ready(T),
+    /// The call must wait on the given wait object, and then be made again.
+    Block(W),
+}
+
+impl<T, W> Blocking<T, W> {
+    /// Convert the result of a finished call with f.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Blocking<U, W> {
+        match self {
+            Blocking::Ready(t) => Blocking::Ready(f(t)),
+            Blocking::Block(w) => Blocking::Block(w),
+        }
+    }
+}
+
+/// Like [SyscallTable], for tables whose handlers may block (see the blocking mode of [crate::syscall_api]).
+pub trait BlockingSyscallTable<Abi: SyscallAbi, Ctx: ?Sized = ()> {
+    /// What a blocked call waits on, e.g. a wait queue.
+    type WaitObject;
+
+    fn handle_call(
+        &self,
+        ctx: &mut Ctx,
+        num: Abi::SyscallNumType,
+        arg: Abi::SyscallArgType,
+    ) -> Blocking<Abi::SyscallRetType, Self::WaitObject>;
+}
+
+/// Like [SyscallTable], for tables whose handlers run asynchronously (see the async mode of
+/// [crate::syscall_api]). The kernel's executor polls the returned future to completion, and returns its output
+/// to the caller.
+pub trait AsyncSyscallTable<Abi: SyscallAbi, Ctx: ?Sized = ()> {
+    fn handle_call<'t>(
+        &'t self,
+        ctx: &'t mut Ctx,
+        num: Abi::SyscallNumType,
+        arg: Abi::SyscallArgType,
+    ) -> impl Future<Output = Abi::SyscallRetType> + 't;
+}
+
+impl<Abi: SyscallAbi, Ctx: ?Sized, T: SyscallTable<Abi, Ctx> + ?Sized> SyscallTable<Abi, Ctx>
+    for &T
+{
+    fn handle_call(
+        &self,
+        ctx: &mut Ctx,
+        num: Abi::SyscallNumType,
+        arg: Abi::SyscallArgType,
+    ) -> Abi::SyscallRetType {
+        (**self).handle_call(ctx, num, arg)
+    }
+}
@@ -0,0 +1,347 @@
+use core::{
+    marker::PhantomData,
+    ptr::null_mut,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use crate::{
+    abi::SyscallAbi,
+    api::{SyscallApi, SyscallFastApi},
+    error::SyscallError,
+    table::{__encode_ret, SyscallTable},
+};
+
+/// The type-erased entry point of a [DynHandler], called with its handler, the table's ABI and context, and the
+/// syscall's number, version and arguments.
+type DynCallFn<'a, Abi, Ctx> = unsafe fn(
+    *const (),
+    &'a Abi,
+    &mut Ctx,
+    <Abi as SyscallAbi>::SyscallNumType,
+    u32,
+    <Abi as SyscallAbi>::SyscallArgType,
+) -> <Abi as SyscallAbi>::SyscallRetType;
+
+/// A type-erased handler for one syscall, which decodes the arguments, calls the handler function, and encodes
+/// the result. Registered with a [DynSyscallTable] whose handlers are given a context of type Ctx.
+pub struct DynHandler<'a, Abi: SyscallAbi, Ctx: ?Sized = ()> {
+    num: Abi::SyscallNumType,
+    handler: *const (),
+    call: DynCallFn<'a, Abi, Ctx>,
+    _pd: PhantomData<&'a ()>,
+}
+
+// Safety: the handler is only ever used through a shared reference, and the constructors require it to be Sync.
+unsafe impl<'a, Abi: SyscallAbi, Ctx: ?Sized> Sync for DynHandler<'a, Abi, Ctx> where
+    Abi::SyscallNumType: Sync
+{
+}
+// Safety: as above.
+unsafe impl<'a, Abi: SyscallAbi, Ctx: ?Sized> Send for DynHandler<'a, Abi, Ctx> where
+    Abi::SyscallNumType: Send
+{
+}
+
+impl<'a, Abi: SyscallAbi, Ctx: ?Sized> DynHandler<'a, Abi, Ctx> {
+    /// Create a handler for syscall T, calling f with the decoded arguments. Calls made with a different
+    /// version of T are rejected as in [crate::syscall_api].
+    pub const fn new<T, F>(f: &'a F) -> Self
+    where
+        T: SyscallApi<'a, Abi>,
+        F: Fn(Abi::SyscallNumType, T) -> Result<T::ReturnType, T::ErrorType> + Sync,
+    {
+        Self {
+            num: T::NUM,
+            handler: f as *const F as *const (),
+            call: call_api::<Abi, Ctx, T, IgnoreContext<F>, T::ErrorType, SyscallErrors>,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Create a handler for fast syscall T, calling f with the arguments.
+    pub const fn new_fast<T, F>(f: &'a F) -> Self
+    where
+        T: SyscallFastApi<'a, Abi>,
+        F: Fn(Abi::SyscallNumType, T) -> T::ReturnType + Sync,
+    {
+        Self {
+            num: T::NUM,
+            handler: f as *const F as *const (),
+            call: call_fast_api::<Abi, Ctx, T, IgnoreContext<F>>,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Like [Self::new], but f is also given the table's context.
+    pub const fn new_with_context<T, F>(f: &'a F) -> Self
+    where
+        T: SyscallApi<'a, Abi>,
+        F: Fn(&mut Ctx, Abi::SyscallNumType, T) -> Result<T::ReturnType, T::ErrorType> + Sync,
+    {
+        Self {
+            num: T::NUM,
+            handler: f as *const F as *const (),
+            call: call_api::<Abi, Ctx, T, WithContext<F>, T::ErrorType, SyscallErrors>,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Like [Self::new_fast], but f is also given the table's context.
+    pub const fn new_fast_with_context<T, F>(f: &'a F) -> Self
+    where
+        T: SyscallFastApi<'a, Abi>,
+        F: Fn(&mut Ctx, Abi::SyscallNumType, T) -> T::ReturnType + Sync,
+    {
+        Self {
+            num: T::NUM,
+            handler: f as *const F as *const (),
+            call: call_fast_api::<Abi, Ctx, T, WithContext<F>>,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Like [Self::new], but for a handler that may be interrupted. As with `restartable = true` in
+    /// [crate::syscall_api], f returns a [SyscallError] rather than the syscall's error type, so that it can
+    /// report [SyscallError::Interrupted] or [SyscallError::Restart].
+    pub const fn new_restartable<T, F>(f: &'a F) -> Self
+    where
+        T: SyscallApi<'a, Abi>,
+        F: Fn(Abi::SyscallNumType, T) -> Result<T::ReturnType, SyscallError<T::ErrorType>> + Sync,
+    {
+        Self {
+            num: T::NUM,
+            handler: f as *const F as *const (),
+            call: call_api::<
+                Abi,
+                Ctx,
+                T,
+                IgnoreContext<F>,
+                SyscallError<T::ErrorType>,
+                RestartableErrors,
+            >,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Like [Self::new_restartable], but f is also given the table's context.
+    pub const fn new_restartable_with_context<T, F>(f: &'a F) -> Self
+    where
+        T: SyscallApi<'a, Abi>,
+        F: Fn(
+                &mut Ctx,
+                Abi::SyscallNumType,
+                T,
+            ) -> Result<T::ReturnType, SyscallError<T::ErrorType>>
+            + Sync,
+    {
+        Self {
+            num: T::NUM,
+            handler: f as *const F as *const (),
+            call: call_api::<
+                Abi,
+                Ctx,
+                T,
+                WithContext<F>,
+                SyscallError<T::ErrorType>,
+                RestartableErrors,
+            >,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Get the number of the syscall this handles.
+    pub fn num(&self) -> Abi::SyscallNumType {
+        self.num
+    }
+}
+
+// Handler functions, either taking the context or not. These are repr(transparent), so a pointer to the
+// function is also a pointer to its wrapper.
+#[repr(transparent)]
+struct IgnoreContext<F>(F);
+#[repr(transparent)]
+struct WithContext<F>(F);
+
+trait DynCall<Ctx: ?Sized, N, T, R> {
+    fn call(&self, ctx: &mut Ctx, num: N, args: T) -> R;
+}
+
+impl<Ctx: ?Sized, N, T, R, F: Fn(N, T) -> R> DynCall<Ctx, N, T, R> for IgnoreContext<F> {
+    fn call(&self, _ctx: &mut Ctx, num: N, args: T) -> R {
+        (self.0)(num, args)
+    }
+}
+
+impl<Ctx: ?Sized, N, T, R, F: Fn(&mut Ctx, N, T) -> R> DynCall<Ctx, N, T, R> for WithContext<F> {
+    fn call(&self, ctx: &mut Ctx, num: N, args: T) -> R {
+        (self.0)(ctx, num, args)
+    }
+}
+
+// How a handler's errors become SyscallErrors. Restartable handlers return SyscallErrors already.
+struct SyscallErrors;
+struct RestartableErrors;
+
+trait DynErrors<E: Copy, HandlerErr> {
+    fn map(err: HandlerErr) -> SyscallError<E>;
+}
+
+impl<E: Copy> DynErrors<E, E> for SyscallErrors {
+    fn map(err: E) -> SyscallError<E> {
+        SyscallError::SyscallError(err)
+    }
+}
+
+impl<E: Copy> DynErrors<E, SyscallError<E>> for RestartableErrors {
+    fn map(err: SyscallError<E>) -> SyscallError<E> {
+        err
+    }
+}
+
+unsafe fn call_api<'a, Abi: SyscallAbi, Ctx: ?Sized, T, F, HandlerErr, M>(
+    handler: *const (),
+    abi: &'a Abi,
+    ctx: &mut Ctx,
+    num: Abi::SyscallNumType,
+    version: u32,
+    args: Abi::SyscallArgType,
+) -> Abi::SyscallRetType
+where
+    T: SyscallApi<'a, Abi>,
+    F: DynCall<Ctx, Abi::SyscallNumType, T, Result<T::ReturnType, HandlerErr>>,
+    M: DynErrors<T::ErrorType, HandlerErr>,
+{
+    // Safety: handler was created from a &'a F, or the function F wraps, in a DynHandler constructor.
+    let f = unsafe { &*(handler as *const F) };
+    let r = if version == T::VERSION {
+        // Safety: passed on from the caller.
+        unsafe {
+            T::with(abi, num, args, |num, args| {
+                f.call(ctx, num, args).map_err(M::map)
+            })
+        }
+    } else if version > T::VERSION {
+        Err(SyscallError::VersionTooNew)
+    } else {
+        Err(SyscallError::VersionTooOld)
+    };
+    __encode_ret(abi, r)
+}
+
+unsafe fn call_fast_api<'a, Abi: SyscallAbi, Ctx: ?Sized, T, F>(
+    handler: *const (),
+    _abi: &'a Abi,
+    ctx: &mut Ctx,
+    num: Abi::SyscallNumType,
+    _version: u32,
+    args: Abi::SyscallArgType,
+) -> Abi::SyscallRetType
+where
+    T: SyscallFastApi<'a, Abi>,
+    F: DynCall<Ctx, Abi::SyscallNumType, T, T::ReturnType>,
+{
+    // Safety: handler was created from a &'a F, or the function F wraps, in a DynHandler constructor.
+    let f = unsafe { &*(handler as *const F) };
+    f.call(ctx, num, args.into()).into()
+}
+
+/// Errors that occur when registering a [DynHandler].
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Ord, Eq, Hash)]
+pub enum RegisterError {
+    /// The handler's syscall number doesn't fit in the table.
+    OutOfRange,
+    /// Another handler is already registered for the syscall number.
+    Occupied,
+}
+
+/// A syscall table with room for syscall numbers below N, where handlers may be registered, replaced, and
+/// unregistered at runtime, e.g. by kernel modules or test fixtures. Looking up a handler takes no locks.
+/// Handlers are borrowed for the lifetime of the table, so a handler that is unregistered during a call to it
+/// remains valid until the call finishes.
+///
+/// Unlike [crate::syscall_api], handlers cannot accept older versions of their syscall, and syscall types
+/// must implement [SyscallApi] for the table's lifetime.
+pub struct DynSyscallTable<'a, Abi: SyscallAbi, const N: usize = 256, Ctx: ?Sized = ()> {
+    abi: &'a Abi,
+    slots: [AtomicPtr<DynHandler<'a, Abi, Ctx>>; N],
+}
+
+impl<'a, Abi: SyscallAbi, const N: usize, Ctx: ?Sized + 'a> DynSyscallTable<'a, Abi, N, Ctx>
+where
+    Abi::SyscallNumType: TryInto<usize>,
+{
+    /// Create a new, empty table.
+    pub fn new(abi: &'a Abi) -> Self {
+        Self {
+            abi,
+            slots: core::array::from_fn(|_| AtomicPtr::new(null_mut())),
+        }
+    }
+
+    fn slot(&self, num: Abi::SyscallNumType) -> Option<&AtomicPtr<DynHandler<'a, Abi, Ctx>>> {
+        self.slots.get(num.try_into().ok()?)
+    }
+
+    /// Register a handler, failing if its number is already registered.
+    pub fn register(&self, handler: &'a DynHandler<'a, Abi, Ctx>) -> Result<(), RegisterError> {
+        let slot = self.slot(handler.num).ok_or(RegisterError::OutOfRange)?;
+        slot.compare_exchange(
+            null_mut(),
+            handler as *const _ as *mut _,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .map(|_| ())
+        .map_err(|_| RegisterError::Occupied)
+    }
+
+    /// Register a handler, returning the handler it replaced, if any.
+    pub fn replace(
+        &self,
+        handler: &'a DynHandler<'a, Abi, Ctx>,
+    ) -> Result<Option<&'a DynHandler<'a, Abi, Ctx>>, RegisterError> {
+        let slot = self.slot(handler.num).ok_or(RegisterError::OutOfRange)?;
+        let old = slot.swap(handler as *const _ as *mut _, Ordering::AcqRel);
+        // Safety: every non-null pointer in the table came from a &'a DynHandler.
+        Ok(unsafe { old.as_ref() })
+    }
+
+    /// Unregister the handler for a syscall number, returning it, if any.
+    pub fn unregister(&self, num: Abi::SyscallNumType) -> Option<&'a DynHandler<'a, Abi, Ctx>> {
+        let old = self.slot(num)?.swap(null_mut(), Ordering::AcqRel);
+        // Safety: every non-null pointer in the table came from a &'a DynHandler.
+        unsafe { old.as_ref() }
+    }
+
+    /// Get the handler registered for a syscall number, if any.
+    pub fn get(&self, num: Abi::SyscallNumType) -> Option<&'a DynHandler<'a, Abi, Ctx>> {
+        let ptr = self.slot(num)?.load(Ordering::Acquire);
+        // Safety: every non-null pointer in the table came from a &'a DynHandler.
+        unsafe { ptr.as_ref() }
+    }
+}
+
+impl<'a, Abi: SyscallAbi, const N: usize, Ctx: ?Sized + 'a> SyscallTable<Abi, Ctx>
+    for DynSyscallTable<'a, Abi, N, Ctx>
+where
+    Abi::SyscallNumType: TryInto<usize>,
+{
+    fn handle_call(
+        &self,
+        ctx: &mut Ctx,
+        num: Abi::SyscallNumType,
+        arg: Abi::SyscallArgType,
+    ) -> Abi::SyscallRetType {
+        let (num, version) = self.abi.split_versioned_num(num);
+        match self.get(num) {
+            // Safety: a syscall table is only handed numbers and arguments that were received together.
+            Some(handler) => unsafe {
+                (handler.call)(handler.handler, self.abi, ctx, num, version, arg)
+            },
+            None => __encode_ret(
+                self.abi,
+                Err::<(), SyscallError<()>>(SyscallError::InvalidNum),
+            ),
+        }
+    }
+}